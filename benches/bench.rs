@@ -58,7 +58,7 @@ fn instrument_test_case(test_case_name: &str) -> String {
     let module = Module::parse_bitcode_from_path(&bitcode_path, &context).unwrap();
 
     match runtime::instrument(test_case_name, &context, &module, true) {
-        Ok(()) => println!("Instrumentation completed successfully"),
+        Ok(stats) => println!("Instrumentation completed successfully\n{}", stats),
         Err(err) => println!("Error occurred: {:?}", err)
     }
 
@@ -0,0 +1,25 @@
+//! Compiles the C++ glue of the pass plugin.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    // Use the same llvm-config as llvm-sys, if given
+    let llvm_config = env::var("LLVM_CONFIG_PATH").unwrap_or_else(|_| "llvm-config".to_string());
+
+    let output = Command::new(&llvm_config)
+        .arg("--cxxflags")
+        .output()
+        .expect("Failed to run llvm-config.");
+    let cxxflags = String::from_utf8(output.stdout).expect("Failed to parse llvm-config output.");
+
+    let mut build = cc::Build::new();
+    build.cpp(true).file("src/sandbox_plugin.cpp");
+    for flag in cxxflags.split_whitespace() {
+        build.flag(flag);
+    }
+    build.compile("sandbox_plugin");
+
+    println!("cargo:rerun-if-changed=src/sandbox_plugin.cpp");
+    println!("cargo:rerun-if-env-changed=LLVM_CONFIG_PATH");
+}
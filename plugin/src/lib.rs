@@ -0,0 +1,155 @@
+//! Exposes instrumentation and verification as a new pass manager plugin.
+//!
+//! `libllvm_sandboxer_plugin.so` can be loaded by `opt` and `clang`:
+//!
+//! ```text
+//! opt -load-pass-plugin=libllvm_sandboxer_plugin.so -passes=sandbox-instrument in.bc -o out.bc
+//! opt -load-pass-plugin=libllvm_sandboxer_plugin.so -passes=sandbox-verify in.bc -disable-output
+//! clang -fpass-plugin=libllvm_sandboxer_plugin.so -c file.c
+//! ```
+//!
+//! The passes are registered by `src/sandbox_plugin.cpp` and process
+//! every function calling the begin marker.  The policy is loaded from the
+//! file in the `LLVM_SANDBOXER_POLICY` environment variable, if set.
+
+use std::env;
+use std::ffi::{c_char, c_int, c_void};
+use std::mem::ManuallyDrop;
+use std::path::Path;
+
+use inkwell::module::Module;
+use inkwell::values::FunctionValue;
+
+extern crate llvm_sys as llvm;
+
+use llvm::prelude::LLVMModuleRef;
+
+use llvm_sandboxer::policy::Policy;
+use llvm_sandboxer::runtime;
+use llvm_sandboxer::static_checks;
+
+/// Environment variable with the path of the policy file.
+const POLICY_ENV_VAR: &str = "LLVM_SANDBOXER_POLICY";
+
+/// Mirrors `llvm::PassPluginLibraryInfo`.
+#[repr(C)]
+pub struct PassPluginLibraryInfo {
+    pub api_version: u32,
+    pub plugin_name: *const c_char,
+    pub plugin_version: *const c_char,
+    pub register_pass_builder_callbacks: extern "C" fn(*mut c_void),
+}
+
+extern "C" {
+    fn llvm_sandboxer_get_plugin_info() -> PassPluginLibraryInfo;
+}
+
+/// Entry point of the plugin, looked up by `opt` and `clang`.
+#[no_mangle]
+pub extern "C" fn llvmGetPassPluginInfo() -> PassPluginLibraryInfo {
+    unsafe { llvm_sandboxer_get_plugin_info() }
+}
+
+/// Loads the policy from `LLVM_SANDBOXER_POLICY`, or the default one.
+fn _load_policy() -> Result<Policy, String> {
+
+    match env::var(POLICY_ENV_VAR) {
+        Ok(path) => Policy::from_file(Path::new(&path)),
+        Err(_) => Ok(Policy::default()),
+    }
+
+}
+
+/// Returns the functions of `module` calling the begin marker.
+fn _get_transactions<'a>(module: &Module<'a>, policy: &Policy) -> Vec<FunctionValue<'a>> {
+
+    module.get_functions()
+        .filter(|function| {
+            function.get_basic_blocks().iter()
+                .flat_map(|bb| bb.get_instructions())
+                .any(|instr| policy.is_begin_marker(instr))
+        })
+        .collect()
+
+}
+
+/// Runs `f` on a module owned by the pass manager.
+///
+/// # Safety
+///
+/// `raw_module` must be a valid module.  The module is not disposed, since
+/// it is owned by the caller, and its context is only borrowed through
+/// `Module::get_context`.
+unsafe fn _with_module<T>(raw_module: LLVMModuleRef, f: impl FnOnce(&Module) -> T) -> T {
+
+    let module: ManuallyDrop<Module> = ManuallyDrop::new(Module::new(raw_module));
+
+    f(&module)
+
+}
+
+/// Instruments every transaction of a module, returns 0 on success.
+#[no_mangle]
+pub extern "C" fn llvm_sandboxer_instrument_module(raw_module: LLVMModuleRef) -> c_int {
+
+    let policy = match _load_policy() {
+        Ok(policy) => policy,
+        Err(err) => {
+            eprintln!("llvm-sandboxer: {}", err);
+            return 1;
+        }
+    };
+
+    unsafe {
+        _with_module(raw_module, |module| {
+
+            for function in _get_transactions(module, &policy) {
+
+                let function_name = function.get_name().to_string_lossy().to_string();
+
+                if let Err(err) = runtime::instrument_module_function(&function_name, module, &policy) {
+                    eprintln!("llvm-sandboxer: failed to instrument {}: {}", function_name, err);
+                    return 1;
+                }
+
+            }
+
+            0
+
+        })
+    }
+
+}
+
+/// Verifies every transaction of a module, returns 0 if all of them pass.
+#[no_mangle]
+pub extern "C" fn llvm_sandboxer_verify_module(raw_module: LLVMModuleRef) -> c_int {
+
+    let policy = match _load_policy() {
+        Ok(policy) => policy,
+        Err(err) => {
+            eprintln!("llvm-sandboxer: {}", err);
+            return 1;
+        }
+    };
+
+    unsafe {
+        _with_module(raw_module, |module| {
+
+            let mut result = 0;
+
+            for function in _get_transactions(module, &policy) {
+
+                if !static_checks::verify_with_policy(module.clone(), function, &policy) {
+                    eprintln!("llvm-sandboxer: {} failed verification", function.get_name().to_string_lossy());
+                    result = 1;
+                }
+
+            }
+
+            result
+
+        })
+    }
+
+}
@@ -0,0 +1,73 @@
+// New pass manager plugin registering the llvm-sandboxer passes:
+//
+//   opt -load-pass-plugin=libllvm_sandboxer_plugin.so -passes=sandbox-instrument
+//   opt -load-pass-plugin=libllvm_sandboxer_plugin.so -passes=sandbox-verify
+//   clang -fpass-plugin=libllvm_sandboxer_plugin.so
+//
+// The passes are implemented in Rust (src/lib.rs), this file only glues
+// them to the PassBuilder.
+
+#include "llvm-c/Types.h"
+#include "llvm/IR/Module.h"
+#include "llvm/IR/PassManager.h"
+#include "llvm/Passes/PassBuilder.h"
+#include "llvm/Passes/PassPlugin.h"
+#include "llvm/Support/ErrorHandling.h"
+
+using namespace llvm;
+
+extern "C" {
+// Defined in src/lib.rs, return 0 on success
+int llvm_sandboxer_instrument_module(LLVMModuleRef module);
+int llvm_sandboxer_verify_module(LLVMModuleRef module);
+}
+
+namespace {
+
+struct SandboxInstrumentPass : PassInfoMixin<SandboxInstrumentPass> {
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &) {
+    if (llvm_sandboxer_instrument_module(wrap(&M)) != 0)
+      report_fatal_error("llvm-sandboxer: instrumentation failed");
+    return PreservedAnalyses::none();
+  }
+};
+
+struct SandboxVerifyPass : PassInfoMixin<SandboxVerifyPass> {
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &) {
+    if (llvm_sandboxer_verify_module(wrap(&M)) != 0)
+      report_fatal_error("llvm-sandboxer: verification failed");
+    return PreservedAnalyses::all();
+  }
+};
+
+void registerCallbacks(PassBuilder &PB) {
+  // -passes=sandbox-instrument / -passes=sandbox-verify
+  PB.registerPipelineParsingCallback(
+      [](StringRef Name, ModulePassManager &MPM,
+         ArrayRef<PassBuilder::PipelineElement>) {
+        if (Name == "sandbox-instrument") {
+          MPM.addPass(SandboxInstrumentPass());
+          return true;
+        }
+        if (Name == "sandbox-verify") {
+          MPM.addPass(SandboxVerifyPass());
+          return true;
+        }
+        return false;
+      });
+
+  // clang -fpass-plugin: instrument before the optimisation pipeline
+  PB.registerPipelineStartEPCallback(
+      [](ModulePassManager &MPM, OptimizationLevel) {
+        MPM.addPass(SandboxInstrumentPass());
+      });
+}
+
+} // namespace
+
+// Called by llvmGetPassPluginInfo in src/lib.rs, so that the entry point
+// is exported by the Rust shared library.
+extern "C" PassPluginLibraryInfo llvm_sandboxer_get_plugin_info() {
+  return {LLVM_PLUGIN_API_VERSION, "llvm-sandboxer", "0.1.0",
+          registerCallbacks};
+}
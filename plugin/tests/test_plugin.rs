@@ -0,0 +1,82 @@
+use llvm_sandboxer::runtime;
+use llvm_sandboxer_plugin as plugin;
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use inkwell::module::Module;
+use inkwell::context::Context;
+
+/// Compile testcases' C sources.
+fn compile_c_files() {
+    let c_files_dir_path = Path::new("../tests/c_files/");
+
+    // Compile the C source file
+    Command::new("make")
+        .args(["-C", &c_files_dir_path.to_string_lossy()])
+        .output()
+        .expect("Failed to compile C source file.");
+}
+
+/// Returns the path of the bitcode of a testcase, compiling it if needed.
+fn get_bitcode_path(testcase_name: &str) -> PathBuf {
+    let bitcode_path = PathBuf::from(format!("../target/tests/{}.bc", testcase_name));
+    if !bitcode_path.exists() {
+        compile_c_files();
+    }
+    bitcode_path
+}
+
+/// Returns the path of the plugin, built next to the test executables.
+fn get_plugin_path() -> PathBuf {
+    let test_exe = env::current_exe().unwrap();
+    let deps_dir = test_exe.parent().unwrap();
+    deps_dir.join(format!("{}llvm_sandboxer_plugin{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX))
+}
+
+#[test]
+fn test_plugin_instrument_module() {
+    let context = Context::create();
+    let module = Module::parse_bitcode_from_path(get_bitcode_path("good_entry_0"), &context).unwrap();
+
+    assert_eq!(plugin::llvm_sandboxer_instrument_module(module.as_mut_ptr()), 0);
+
+    // The module and its context are still usable after the pass
+    assert!(runtime::is_instrumented(&module, "good_entry_0"));
+    assert!(module.verify().is_ok());
+}
+
+#[test]
+fn test_plugin_verify_module() {
+    let context = Context::create();
+
+    let module = Module::parse_bitcode_from_path(get_bitcode_path("good_entry_0"), &context).unwrap();
+    assert_eq!(plugin::llvm_sandboxer_verify_module(module.as_mut_ptr()), 0);
+
+    let module = Module::parse_bitcode_from_path(get_bitcode_path("bad_entry_4"), &context).unwrap();
+    assert_eq!(plugin::llvm_sandboxer_verify_module(module.as_mut_ptr()), 1);
+}
+
+#[test]
+fn test_plugin_load_with_opt() {
+    let plugin_path = get_plugin_path();
+    assert!(plugin_path.exists(), "Plugin not found at {}", plugin_path.display());
+
+    let output_path = "../target/tests/instrumented/good_entry_0_plugin.bc";
+
+    let output = Command::new("opt")
+        .arg(format!("-load-pass-plugin={}", plugin_path.display()))
+        .arg("-passes=sandbox-instrument")
+        .arg(get_bitcode_path("good_entry_0"))
+        .args(["-o", output_path])
+        .output()
+        .expect("Cannot execute opt.");
+
+    println!("{:?}", output);
+    assert!(output.status.success());
+
+    // The instrumented module was written by opt
+    let context = Context::create();
+    let module = Module::parse_bitcode_from_path(output_path, &context).unwrap();
+    assert!(runtime::is_instrumented(&module, "good_entry_0"));
+}
@@ -0,0 +1,159 @@
+//! Optimises instrumented modules and generates native code.
+//!
+//! The runtime checks added by `runtime::instrument` are plain LLVM IR, so
+//! running the standard optimisation pipeline after instrumentation hoists,
+//! merges and removes them like any other code.  Measuring the cost of the
+//! checks on optimised code gives realistic numbers.
+//!
+//! Instrumented modules can then be written as object files for the host or
+//! for another target, and linked into executables with the system linker.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+use inkwell::module::Module;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple};
+use inkwell::OptimizationLevel;
+
+/// Converts an optimisation level `n` (as in `-O<n>`) to an `OptimizationLevel`.
+fn _get_optimization_level(opt_level: u32) -> Result<OptimizationLevel, String> {
+
+    match opt_level {
+        0 => Ok(OptimizationLevel::None),
+        1 => Ok(OptimizationLevel::Less),
+        2 => Ok(OptimizationLevel::Default),
+        3 => Ok(OptimizationLevel::Aggressive),
+        other => Err(format!("Expected optimisation level between 0 and 3, found {}", other)),
+    }
+
+}
+
+/// Creates a target machine.
+///
+/// # Arguments
+///
+/// * `triple` - The target triple, e.g. `aarch64-unknown-linux-gnu`, or
+/// `None` for the host.
+/// * `cpu` - The target CPU, e.g. `skylake`, or `None` for the host CPU when
+/// targeting the host and a generic CPU otherwise.
+/// * `opt_level` - The code generation optimisation level, between 0 and 3.
+pub fn create_target_machine(triple: Option<&str>, cpu: Option<&str>, opt_level: u32) -> Result<TargetMachine, String> {
+
+    let host_triple = TargetMachine::normalize_triple(&TargetMachine::get_default_triple());
+    let triple = match triple {
+        Some(triple) => TargetMachine::normalize_triple(&TargetTriple::create(triple)),
+        None => TargetMachine::normalize_triple(&host_triple),
+    };
+    let is_host = triple == host_triple;
+
+    // Cross compilation needs every target, not only the native one
+    if is_host {
+        Target::initialize_native(&InitializationConfig::default())
+            .map_err(|e| format!("Failed to initialize native target: {}", e))?;
+    } else {
+        Target::initialize_all(&InitializationConfig::default());
+    }
+
+    let target = Target::from_triple(&triple)
+        .map_err(|e| format!("Failed to get target for {}: {}", triple, e))?;
+
+    // Host features only make sense for the host CPU
+    let (cpu, features) = match cpu {
+        Some(cpu) => (cpu.to_string(), String::new()),
+        None if is_host => (
+            TargetMachine::get_host_cpu_name().to_string_lossy().to_string(),
+            TargetMachine::get_host_cpu_features().to_string_lossy().to_string(),
+        ),
+        None => ("generic".to_string(), String::new()),
+    };
+
+    target.create_target_machine(
+        &triple,
+        &cpu,
+        &features,
+        _get_optimization_level(opt_level)?,
+        RelocMode::PIC,
+        CodeModel::Default,
+    ).ok_or_else(|| format!("Failed to create target machine for {} ({})", triple, cpu))
+
+}
+
+/// Sets the target triple and data layout of a module to the ones of the
+/// target machine.
+fn _set_target(module: &Module, target_machine: &TargetMachine) {
+
+    module.set_triple(&target_machine.get_triple());
+    module.set_data_layout(&target_machine.get_target_data().get_data_layout());
+
+}
+
+/// Runs LLVM's standard optimisation pipeline (`default<O<n>>`) on a module.
+///
+/// The module is retargeted to `target_machine` before the passes run, so
+/// that they optimise for the target the code is generated for.
+///
+/// # Arguments
+///
+/// * `module` - The module to optimise, e.g. after instrumentation.
+/// * `target_machine` - The target machine, used for target specific passes.
+/// * `opt_level` - The optimisation level, between 0 and 3.
+pub fn optimize(module: &Module, target_machine: &TargetMachine, opt_level: u32) -> Result<(), String> {
+
+    // Check the optimisation level
+    _get_optimization_level(opt_level)?;
+
+    _set_target(module, target_machine);
+
+    module.run_passes(&format!("default<O{}>", opt_level), target_machine, PassBuilderOptions::create())
+        .map_err(|e| format!("Failed to optimise module: {}", e))
+
+}
+
+/// Writes a module as a native object file.
+///
+/// The target triple and data layout of the module are set to the ones of
+/// the target machine.
+///
+/// # Arguments
+///
+/// * `module` - The module to compile, e.g. after instrumentation.
+/// * `target_machine` - The target machine generating the code.
+/// * `object_path` - The path of the object file.
+pub fn write_object_file(module: &Module, target_machine: &TargetMachine, object_path: &Path) -> Result<(), String> {
+
+    _set_target(module, target_machine);
+
+    target_machine.write_to_file(module, FileType::Object, object_path)
+        .map_err(|e| format!("Failed to write object file {}: {}", object_path.display(), e))
+
+}
+
+/// Links an object file into an executable with the system linker.
+///
+/// The linker is invoked through the C compiler driver, `cc` or the one in
+/// the `CC` environment variable, so that the C runtime is linked too.
+///
+/// # Arguments
+///
+/// * `object_path` - The path of the object file.
+/// * `exec_path` - The path of the executable.
+pub fn link_executable(object_path: &Path, exec_path: &Path) -> Result<(), String> {
+
+    let linker = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let output = Command::new(&linker)
+        .arg(object_path)
+        .arg("-o")
+        .arg(exec_path)
+        .output()
+        .map_err(|e| format!("Failed to run linker {}: {}", linker, e))?;
+
+    if !output.status.success() {
+        return Err(format!("Failed to link {}: {}", exec_path.display(), String::from_utf8_lossy(&output.stderr)));
+    }
+
+    Ok(())
+
+}
@@ -1,2 +1,10 @@
 pub mod static_checks;
+pub mod symbolic;
+pub mod ranges;
+#[cfg(feature = "smt")]
+pub mod smt;
 pub mod runtime;
+pub mod stats;
+pub mod policy;
+pub mod report;
+pub mod codegen;
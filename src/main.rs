@@ -1,8 +1,26 @@
 use std::env;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use inkwell::context::Context;
 use inkwell::module::Module;
 use llvm_sandboxer::runtime;
+use llvm_sandboxer::static_checks;
+use llvm_sandboxer::report::VerificationReport;
+use llvm_sandboxer::codegen;
+use llvm_sandboxer::stats::InstrumentationStats;
+use llvm_sandboxer::policy::{CallPolicy, Policy};
+
+/// Returns the value following option `name`, exiting if it is missing.
+fn get_option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    let i = args.iter().position(|arg| arg == name)?;
+    match args.get(i + 1) {
+        Some(value) => Some(value),
+        None => {
+            eprintln!("Missing value after {}", name);
+            std::process::exit(1);
+        }
+    }
+}
 
 fn main() {
     // Get command-line arguments
@@ -10,16 +28,90 @@ fn main() {
 
     // Check if the expected number of arguments are provided
     if args.len() < 3 {
-        eprintln!("Usage: {} <file_path> <function_to_check>", args[0]);
+        eprintln!("Usage: {} <file_path> <function_to_check> [--policy <file>] [-s] [--stack-bounds] [--diagnostics] [--read-constants] [--allow <f,...> | --deny <f,...>] [-O<n>] [--target <triple>] [--cpu <name>] [--emit-obj <file>] [--emit-exe <file>] [--stats] [--stats-json <file>] [--verify [--report-json <file>] [--report-sarif <file>]]", args[0]);
         std::process::exit(1);
     }
 
-    let mut run_static_analysis = false;
+    // Load the sandbox rules from the policy file, if any
+    let mut policy = match args.iter().position(|arg| arg == "--policy") {
+        Some(i) => {
+            let Some(policy_path) = args.get(i + 1) else {
+                eprintln!("Missing file path after --policy");
+                std::process::exit(1);
+            };
+            match Policy::from_file(Path::new(policy_path)) {
+                Ok(policy) => policy,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => Policy::default(),
+    };
 
+    // Command-line options override the policy file
     if args.contains(&String::from("-s")) {
-        run_static_analysis = true;
+        policy.static_analysis = true;
+    }
+
+    // Check stack accesses against the stack allocation
+    if args.contains(&String::from("--stack-bounds")) {
+        policy.check_stack_bounds = true;
+    }
+
+    // Print the site of failed checks before aborting
+    if args.contains(&String::from("--diagnostics")) {
+        policy.diagnostics = true;
+    }
+
+    // Load from constant globals without checks
+    if args.contains(&String::from("--read-constants")) {
+        policy.read_constant_globals = true;
+    }
+
+    // Functions that can be called inside the transaction,
+    // given as comma separated lists: `--allow f,g` or `--deny f,g`
+    for (i, arg) in args.iter().enumerate() {
+        if arg == "--allow" || arg == "--deny" {
+            let Some(names) = args.get(i + 1) else {
+                eprintln!("Missing function list after {}", arg);
+                std::process::exit(1);
+            };
+            let names: Vec<String> = names.split(',').map(|name| name.to_string()).collect();
+            policy.calls = if arg == "--allow" { CallPolicy::Allow(names) } else { CallPolicy::Deny(names) };
+        }
     }
 
+    // Optimisation level of the pipeline run after instrumentation, if any
+    let opt_level: Option<u32> = match args.iter().find(|arg| arg.starts_with("-O")) {
+        Some(arg) => match arg[2..].parse() {
+            Ok(level) => Some(level),
+            Err(_) => {
+                eprintln!("Invalid optimisation level: {}", arg);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // Target of the generated code, the host by default
+    let target_triple = get_option_value(&args, "--target");
+    let target_cpu = get_option_value(&args, "--cpu");
+
+    // Native code to generate, if any
+    let object_path = get_option_value(&args, "--emit-obj").map(PathBuf::from);
+    let exec_path = get_option_value(&args, "--emit-exe").map(PathBuf::from);
+
+    // Report of the instrumented accesses
+    let print_stats = args.contains(&String::from("--stats"));
+    let stats_path = get_option_value(&args, "--stats-json");
+
+    // Verify the function instead of instrumenting it
+    let verify_only = args.contains(&String::from("--verify"));
+    let report_json_path = get_option_value(&args, "--report-json");
+    let report_sarif_path = get_option_value(&args, "--report-sarif");
+
     // Get the file path
     let file_path = &args[1];
     let function_name = &args[2];
@@ -31,16 +123,104 @@ fn main() {
     // Parse LLVM
     let module = Module::parse_bitcode_from_path(path, &context).unwrap();
 
-    match runtime::instrument(function_name, &context, &module, run_static_analysis) {
-        Ok(()) => println!("Instrumentation completed successfully"),
-        Err(err) => println!("Error occurred: {:?}", err)
+    if verify_only {
+        let Some(function) = module.get_function(function_name) else {
+            eprintln!("Function {} not found", function_name);
+            std::process::exit(1);
+        };
+
+        let report = VerificationReport { violations: static_checks::get_violations(module.clone(), function, &policy) };
+
+        for violation in &report.violations {
+            match &violation.location {
+                Some(location) => println!("{}:{}:{}: [{}] {}", location.file, location.line, location.column, violation.rule_id, violation.message),
+                None => println!("{}: [{}] {}: {}", violation.function, violation.rule_id, violation.message, violation.instruction),
+            }
+        }
+
+        // Write the reports
+        let reports = [(report_json_path, report.to_json()), (report_sarif_path, report.to_sarif())];
+        for (report_path, serialised) in reports {
+            let Some(report_path) = report_path else { continue };
+            let result = serialised
+                .and_then(|serialised| fs::write(report_path, serialised).map_err(|e| format!("Failed to write {}: {}", report_path, e)));
+            if let Err(err) = result {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+
+        if !report.passed() {
+            std::process::exit(1);
+        }
+        println!("Verification passed");
+        return;
     }
 
-    module.print_to_file("out.ll");
+    match runtime::instrument_with_policy(function_name, &context, &module, &policy) {
+        Ok(stats) => {
+            println!("Instrumentation completed successfully");
+
+            if print_stats {
+                print!("{}", stats);
+            }
 
-    match module.verify() {
-        Ok(()) => (),
-        Err(e) => println!("{}", e.to_string())
+            if let Some(stats_path) = stats_path {
+                let result = InstrumentationStats::to_json(&[stats])
+                    .and_then(|json| fs::write(stats_path, json).map_err(|e| format!("Failed to write {}: {}", stats_path, e)));
+                if let Err(err) = result {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(err) => {
+            // Nothing is written, an uninstrumented module must not look sandboxed
+            eprintln!("Failed to instrument {}: {}", function_name, err);
+            std::process::exit(1);
+        }
     }
 
+    // A broken module must not be compiled
+    if let Err(e) = module.verify() {
+        eprintln!("Instrumented module is invalid: {}", e.to_string());
+        std::process::exit(1);
+    }
+
+    // Optimise the instrumented module
+    if opt_level.is_some() || object_path.is_some() || exec_path.is_some() {
+        let target_machine = match codegen::create_target_machine(target_triple, target_cpu, opt_level.unwrap_or(0)) {
+            Ok(target_machine) => target_machine,
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        };
+
+        if let Some(opt_level) = opt_level {
+            if let Err(err) = codegen::optimize(&module, &target_machine, opt_level) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+
+        // Emit the object file, linking it if an executable is requested
+        let result = match (&object_path, &exec_path) {
+            (Some(object_path), None) => codegen::write_object_file(&module, &target_machine, object_path),
+            (object_path, Some(exec_path)) => {
+                // The object file linked is kept at the path given, if any
+                let object_path = object_path.clone().unwrap_or_else(|| exec_path.with_extension("o"));
+                codegen::write_object_file(&module, &target_machine, &object_path)
+                    .and_then(|()| codegen::link_executable(&object_path, exec_path))
+            }
+            (None, None) => Ok(()),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+
+    module.print_to_file("out.ll");
+
 }
@@ -0,0 +1,315 @@
+//! Policies describing the sandbox rules of micro-transactions.
+//!
+//! A [`Policy`] gathers the marker functions delimiting a transaction, the
+//! function called when a runtime check fails, the globals exempt from checks
+//! (optionally every `constant` global, for loads), the functions a
+//! transaction may call ([`CallPolicy`]) and the optional analyses.  Policies
+//! can be loaded from TOML files, so that sandbox rules can be versioned
+//! alongside the code they protect:
+//!
+//! ```toml
+//! violation_handler = "abort"
+//! exempt_globals = ["config"]
+//! static_analysis = true
+//! check_stack_bounds = false
+//! diagnostics = false
+//! read_constant_globals = false
+//!
+//! [markers]
+//! begin = "utx1"
+//! end = "utx0"
+//!
+//! [calls]
+//! allow = ["sqrt"]
+//! ```
+//!
+//! The policy is enforced by `static_checks::verify_with_policy` (the function
+//! is rejected) and by `runtime::instrument_with_policy` (the function is not
+//! instrumented).
+
+use std::fs;
+use std::path::Path;
+
+use inkwell::values::{FunctionValue, GlobalValue, InstructionValue};
+use inkwell::values::BasicValueEnum::PointerValue as PV;
+use inkwell::values::InstructionOpcode::{Call, CallBr, Invoke};
+use serde::Deserialize;
+
+/// Intrinsics that do not access memory, or whose accesses are checked, and
+/// can always be called.
+const ALWAYS_ALLOWED_PREFIXES: [&str; 8] = [
+    "llvm.dbg.",
+    "llvm.lifetime.",
+    "llvm.assume",
+    "llvm.experimental.noalias.scope.decl",
+    "llvm.masked.load.",
+    "llvm.masked.store.",
+    "llvm.masked.gather.",
+    "llvm.masked.scatter.",
+];
+
+/// Kinds of accesses that only read memory (see `static_checks::get_access_kind`).
+const READ_ACCESS_KINDS: [&str; 2] = ["load", "masked_load"];
+
+/// Functions that can be called inside a transaction.
+///
+/// In TOML, either `calls = "allow_all"`, `calls = { allow = [...] }` or
+/// `calls = { deny = [...] }`.
+///
+/// Called functions are not instrumented, so the memory they access (e.g.
+/// through `memcpy` or the `llvm.memcpy` intrinsic) is not checked.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallPolicy {
+    /// Every function can be called.
+    #[default]
+    AllowAll,
+    /// Only the listed functions can be called.
+    Allow(Vec<String>),
+    /// Every function except the listed ones can be called.
+    Deny(Vec<String>),
+}
+
+impl CallPolicy {
+
+    /// Checks if a call to `callee` is allowed by the policy.
+    ///
+    /// `callee` is `None` for indirect calls, which are only allowed by
+    /// [`CallPolicy::AllowAll`] since the called function cannot be known.
+    pub fn is_allowed(&self, callee: Option<&str>) -> bool {
+
+        let Some(callee) = callee else {
+            return *self == CallPolicy::AllowAll;
+        };
+
+        if ALWAYS_ALLOWED_PREFIXES.iter().any(|prefix| callee.starts_with(prefix)) {
+            return true;
+        }
+
+        match self {
+            CallPolicy::AllowAll => true,
+            CallPolicy::Allow(names) => names.iter().any(|name| name == callee),
+            CallPolicy::Deny(names) => !names.iter().any(|name| name == callee),
+        }
+
+    }
+
+}
+
+/// Names of the marker functions delimiting a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Markers {
+    /// Starts the transaction and protects a memory region, e.g. `utx1(ptr, len)`.
+    pub begin: String,
+    /// Ends the transaction, e.g. `utx0()`.
+    pub end: String,
+}
+
+impl Default for Markers {
+
+    fn default() -> Self {
+        Markers {
+            begin: "utx1".to_string(),
+            end: "utx0".to_string(),
+        }
+    }
+
+}
+
+/// Sandbox rules for verification and instrumentation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Marker functions delimiting a transaction.
+    pub markers: Markers,
+    /// Function called when a runtime check fails, of type `void ()`.  An
+    /// existing declaration in the module is reused if its type matches.
+    pub violation_handler: String,
+    /// Globals that can be accessed without checks, by accesses proven
+    /// inside them (see `static_checks::is_exempt_access`).
+    pub exempt_globals: Vec<String>,
+    /// Functions that can be called inside a transaction.
+    pub calls: CallPolicy,
+    /// Skips runtime checks of accesses proven safe statically, and rejects
+    /// accesses proven unsafe at compile time.
+    pub static_analysis: bool,
+    /// Checks accesses to stack arrays against the bounds of the allocation,
+    /// instead of skipping them, except the ones at a constant offset proven
+    /// inside it.
+    pub check_stack_bounds: bool,
+    /// Prints the site, the accessed memory and the active region to stderr
+    /// before calling the violation handler when a runtime check fails.
+    pub diagnostics: bool,
+    /// Allows loads from `constant` globals (string literals, lookup tables)
+    /// without checks, when they are proven inside the global like for
+    /// `exempt_globals`.  Stores to them are still checked.
+    pub read_constant_globals: bool,
+}
+
+impl Default for Policy {
+
+    fn default() -> Self {
+        Policy {
+            markers: Markers::default(),
+            violation_handler: "abort".to_string(),
+            exempt_globals: Vec::new(),
+            calls: CallPolicy::AllowAll,
+            static_analysis: false,
+            check_stack_bounds: false,
+            diagnostics: false,
+            read_constant_globals: false,
+        }
+    }
+
+}
+
+impl Policy {
+
+    /// Parses a policy from a TOML string.
+    ///
+    /// Missing fields take their default value.
+    pub fn from_toml(toml_str: &str) -> Result<Policy, String> {
+        toml::from_str(toml_str).map_err(|e| format!("Failed to parse policy: {}", e))
+    }
+
+    /// Loads a policy from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Policy, String> {
+
+        let toml_str = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read policy file {}: {}", path.display(), e))?;
+
+        Policy::from_toml(&toml_str)
+
+    }
+
+    /// Checks if `instr` is a `call` to the marker starting a transaction.
+    pub fn is_begin_marker(&self, instr: InstructionValue) -> bool {
+        instr.get_opcode() == Call && get_called_function_name(instr).as_deref() == Some(self.markers.begin.as_str())
+    }
+
+    /// Checks if `instr` is a `call` to the marker ending a transaction.
+    pub fn is_end_marker(&self, instr: InstructionValue) -> bool {
+        instr.get_opcode() == Call && get_called_function_name(instr).as_deref() == Some(self.markers.end.as_str())
+    }
+
+    /// Checks if `name` is a global exempt from checks.
+    pub fn is_exempt_global(&self, name: &str) -> bool {
+        self.exempt_globals.iter().any(|global| global == name)
+    }
+
+    /// Checks if an access of `kind` (see `static_checks::get_access_kind`) to
+    /// `global` is exempt from checks: the global is exempt, or the access
+    /// loads from a `constant` global and `read_constant_globals` is set.
+    pub fn is_exempt_access(&self, global: GlobalValue, kind: &str) -> bool {
+
+        if self.is_exempt_global(global.get_name().to_str().unwrap_or_default()) {
+            return true;
+        }
+
+        self.read_constant_globals && global.is_constant() && READ_ACCESS_KINDS.contains(&kind)
+
+    }
+
+}
+
+/// Checks if `instr` calls a function: a `call`, an `invoke` or a `callbr`.
+pub fn is_call(instr: InstructionValue) -> bool {
+    matches!(instr.get_opcode(), Call | Invoke | CallBr)
+}
+
+/// Returns the name of the function called by a call instruction (see
+/// [`is_call`]).
+///
+/// Returns `None` if the instruction is not a call or if the call is
+/// indirect (e.g. through a function pointer or inline assembly).
+pub fn get_called_function_name(instr: InstructionValue) -> Option<String> {
+
+    if !is_call(instr) {
+        return None;
+    }
+
+    // The called value is the last operand of a call, invoke or callbr instruction
+    let callee = match instr.get_operand(instr.get_num_operands() - 1)?.left()? {
+        PV(ptr) => ptr,
+        _ => return None,
+    };
+
+    // Direct calls reference a function, which is a named constant
+    if !callee.is_const() || callee.get_name().to_bytes().is_empty() {
+        return None;
+    }
+
+    callee.get_name().to_str().ok().map(|name| name.to_string())
+
+}
+
+/// Finds the calls inside the transactions of `function` that are not
+/// allowed by the call policy.
+///
+/// A transaction goes from a call to the begin marker to the next call to the
+/// end marker, in the order of the function, so that e.g. a buffer can be
+/// allocated before the transaction and freed after it.
+///
+/// # Returns
+///
+/// Returns the calls that are not allowed with an error message, in the
+/// order of the function.
+pub fn get_disallowed_calls<'a>(function: FunctionValue<'a>, policy: &Policy) -> Vec<(InstructionValue<'a>, String)> {
+
+    let mut disallowed_calls: Vec<(InstructionValue, String)> = Vec::new();
+
+    // Between a begin and an end marker
+    let mut in_transaction = false;
+
+    // Iterate over the basic blocks in the function
+    for bb in function.get_basic_blocks() {
+
+        // Iterate over the instructions in the basic block
+        for instr in bb.get_instructions() {
+
+            if policy.is_begin_marker(instr) {
+                in_transaction = true;
+                continue;
+            }
+
+            if policy.is_end_marker(instr) {
+                in_transaction = false;
+                continue;
+            }
+
+            if !is_call(instr) || !in_transaction {
+                continue;
+            }
+
+            let callee = get_called_function_name(instr);
+
+            if !policy.calls.is_allowed(callee.as_deref()) {
+                disallowed_calls.push((instr, match callee {
+                    Some(name) => format!("Call to '{}' is not allowed inside a transaction", name),
+                    None => "Indirect call is not allowed inside a transaction".to_string(),
+                }));
+            }
+
+        }
+
+    }
+
+    disallowed_calls
+
+}
+
+/// Checks that every call inside the transactions of `function` is allowed
+/// by the call policy (see [`get_disallowed_calls`]).
+///
+/// # Returns
+///
+/// Returns an error naming the first call that is not allowed.
+pub fn check_calls(function: FunctionValue, policy: &Policy) -> Result<(), String> {
+
+    match get_disallowed_calls(function, policy).into_iter().next() {
+        Some((_, err)) => Err(err),
+        None => Ok(()),
+    }
+
+}
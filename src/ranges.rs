@@ -0,0 +1,574 @@
+//! Facts on integer values, gathered from branch conditions.
+//!
+//! In `if (index < 100) { ... }`, the conditional branch on
+//! `icmp ult i64 %index, 100` guarantees that `0 <= index <= 99` in every
+//! block only reachable through its true edge, i.e. dominated by it.  These
+//! facts bound the variables of symbolic offsets (see
+//! `symbolic::check_access`), so that guarded accesses can be proven safe.
+//! The comparisons themselves are kept for solvers able to reason about
+//! relations between variables.
+//!
+//! The induction variables of loops are bounded too, from their start value,
+//! their step and the exit condition of the loop, e.g. `0 <= i <= 99` in the
+//! body of `for (i = 0; i < 100; i++)`.
+
+use std::collections::HashMap;
+
+use inkwell::basic_block::BasicBlock;
+use inkwell::values::{FunctionValue, IntValue, PhiValue};
+use inkwell::values::BasicValueEnum::IntValue as IV;
+use inkwell::values::InstructionOpcode::{Add, And, Or, Br, ICmp, Phi, Select, SExt, ZExt};
+use inkwell::IntPredicate;
+
+/// Inclusive range `(min, max)` of the signed values of an integer.
+pub type Range = (i64, i64);
+
+/// Known ranges of integer values.
+pub type Ranges<'a> = HashMap<IntValue<'a>, Range>;
+
+/// Comparison `lhs <predicate> rhs` known to hold, as `(predicate, lhs, rhs)`.
+pub type Condition<'a> = (IntPredicate, IntValue<'a>, IntValue<'a>);
+
+/// Facts known to hold in a block.
+#[derive(Debug, Clone, Default)]
+pub struct Facts<'a> {
+    /// Comparisons of the branches dominating the block.
+    pub conditions: Vec<Condition<'a>>,
+    /// Ranges of integer values, from the comparisons with a constant.
+    pub ranges: Ranges<'a>,
+}
+
+/// Range of an integer without facts.
+pub const FULL_RANGE: Range = (i64::MIN, i64::MAX);
+
+/// Intersects two ranges.
+pub fn intersect(a: Range, b: Range) -> Range {
+    (a.0.max(b.0), a.1.min(b.1))
+}
+
+/// Returns the blocks a block can branch to, once per edge.
+fn _get_successors(bb: BasicBlock) -> Vec<BasicBlock> {
+
+    let Some(terminator) = bb.get_terminator() else {
+        return Vec::new();
+    };
+
+    let mut successors: Vec<BasicBlock> = Vec::new();
+
+    // One entry per edge, a block reached by two edges is listed twice
+    for i in 0..terminator.get_num_operands() {
+        if let Some(successor) = terminator.get_operand(i).and_then(|operand| operand.right()) {
+            successors.push(successor);
+        }
+    }
+
+    successors
+
+}
+
+/// Computes the dominators of every block, with the iterative data-flow
+/// algorithm: `dominators[i][j]` is true if block `j` dominates block `i`.
+fn _get_dominators(blocks: &[BasicBlock], predecessors: &[Vec<usize>]) -> Vec<Vec<bool>> {
+
+    let n = blocks.len();
+
+    // The entry block is only dominated by itself, the others by every block at first
+    let mut dominators: Vec<Vec<bool>> = (0..n).map(|i| (0..n).map(|j| i != 0 || j == 0).collect()).collect();
+
+    let mut changed = true;
+
+    while changed {
+
+        changed = false;
+
+        for i in 1..n {
+
+            // Intersection of the dominators of the predecessors, plus the block itself
+            let mut new_dominators: Vec<bool> = vec![!predecessors[i].is_empty(); n];
+            for &p in &predecessors[i] {
+                for (dominated, dominated_by_p) in new_dominators.iter_mut().zip(&dominators[p]) {
+                    *dominated &= *dominated_by_p;
+                }
+            }
+            new_dominators[i] = true;
+
+            if new_dominators != dominators[i] {
+                dominators[i] = new_dominators;
+                changed = true;
+            }
+
+        }
+
+    }
+
+    dominators
+
+}
+
+/// Returns the range of `x` such that `x <predicate> constant` holds.
+fn _get_predicate_range(predicate: IntPredicate, constant: i64) -> Option<Range> {
+
+    match predicate {
+        IntPredicate::EQ => Some((constant, constant)),
+        // Unsigned comparisons with a non negative constant bound the signed value too
+        IntPredicate::ULT if constant > 0 => Some((0, constant - 1)),
+        IntPredicate::ULE if constant >= 0 => Some((0, constant)),
+        IntPredicate::SLT if constant > i64::MIN => Some((i64::MIN, constant - 1)),
+        IntPredicate::SLE => Some((i64::MIN, constant)),
+        IntPredicate::SGT if constant < i64::MAX => Some((constant + 1, i64::MAX)),
+        IntPredicate::SGE => Some((constant, i64::MAX)),
+        _ => None,
+    }
+
+}
+
+/// Returns the predicate that holds when `predicate` does not.
+fn _get_inverse_predicate(predicate: IntPredicate) -> IntPredicate {
+
+    match predicate {
+        IntPredicate::EQ => IntPredicate::NE,
+        IntPredicate::NE => IntPredicate::EQ,
+        IntPredicate::ULT => IntPredicate::UGE,
+        IntPredicate::UGE => IntPredicate::ULT,
+        IntPredicate::ULE => IntPredicate::UGT,
+        IntPredicate::UGT => IntPredicate::ULE,
+        IntPredicate::SLT => IntPredicate::SGE,
+        IntPredicate::SGE => IntPredicate::SLT,
+        IntPredicate::SLE => IntPredicate::SGT,
+        IntPredicate::SGT => IntPredicate::SLE,
+    }
+
+}
+
+/// Returns the predicate `p'` such that `a p b` is `b p' a`.
+fn _get_swapped_predicate(predicate: IntPredicate) -> IntPredicate {
+
+    match predicate {
+        IntPredicate::ULT => IntPredicate::UGT,
+        IntPredicate::UGT => IntPredicate::ULT,
+        IntPredicate::ULE => IntPredicate::UGE,
+        IntPredicate::UGE => IntPredicate::ULE,
+        IntPredicate::SLT => IntPredicate::SGT,
+        IntPredicate::SGT => IntPredicate::SLT,
+        IntPredicate::SLE => IntPredicate::SGE,
+        IntPredicate::SGE => IntPredicate::SLE,
+        other => other,
+    }
+
+}
+
+/// Gathers the comparisons implied by a condition being `taken` (true) or not.
+fn _get_conditions<'a>(condition: IntValue<'a>, taken: bool, conditions: &mut Vec<Condition<'a>>) {
+
+    let Some(instr) = condition.as_instruction() else {
+        return;
+    };
+
+    let operand = |i: u32| match instr.get_operand(i).and_then(|o| o.left()) {
+        Some(IV(value)) => Some(value),
+        _ => None,
+    };
+
+    let (Some(lhs), Some(rhs)) = (operand(0), operand(1)) else {
+        return;
+    };
+
+    match instr.get_opcode() {
+
+        // Both conditions hold if their conjunction is true,
+        // and neither holds if their disjunction is false
+        And if taken => {
+            _get_conditions(lhs, taken, conditions);
+            _get_conditions(rhs, taken, conditions);
+        }
+
+        Or if !taken => {
+            _get_conditions(lhs, taken, conditions);
+            _get_conditions(rhs, taken, conditions);
+        }
+
+        // Logical `a && b` is `select i1 a, i1 b, i1 false`,
+        // and `a || b` is `select i1 a, i1 true, i1 b`
+        Select => {
+            let Some(false_value) = operand(2) else { return };
+            if taken && false_value.get_zero_extended_constant() == Some(0) {
+                _get_conditions(lhs, taken, conditions);
+                _get_conditions(rhs, taken, conditions);
+            } else if !taken && rhs.get_zero_extended_constant() == Some(1) {
+                _get_conditions(lhs, taken, conditions);
+                _get_conditions(false_value, taken, conditions);
+            }
+        }
+
+        ICmp => {
+
+            let Some(predicate) = instr.get_icmp_predicate() else {
+                return;
+            };
+
+            let predicate = if taken { predicate } else { _get_inverse_predicate(predicate) };
+
+            conditions.push((predicate, lhs, rhs));
+
+        }
+
+        _ => (),
+
+    }
+
+}
+
+/// Returns the range of the value compared with a constant in a condition.
+fn _get_condition_range<'a>(condition: &Condition<'a>) -> Option<(IntValue<'a>, Range)> {
+
+    let (predicate, lhs, rhs) = *condition;
+
+    // Bring the constant to the right hand side
+    let (value, predicate, constant) = match (lhs.get_sign_extended_constant(), rhs.get_sign_extended_constant()) {
+        (None, Some(constant)) => (lhs, predicate, constant),
+        (Some(constant), None) => (rhs, _get_swapped_predicate(predicate), constant),
+        _ => return None,
+    };
+
+    Some((value, _get_predicate_range(predicate, constant)?))
+
+}
+
+/// Returns the range of the value `x` such that the comparison holds, if
+/// the comparison is between `x` and a constant.
+fn _get_compared_range<'a>(condition: &Condition<'a>, x: IntValue<'a>) -> Option<Range> {
+
+    let (value, range) = _get_condition_range(condition)?;
+
+    if value != x {
+        return None;
+    }
+
+    Some(range)
+
+}
+
+/// Returns the range of an induction variable of a loop, from its start
+/// value, its step and the exit condition of the loop.
+///
+/// The variable must start at a constant and be incremented by a constant
+/// step in the latch of the loop, which exits when the incremented value
+/// fails a comparison with a constant, e.g. `for (i = 0; i < 1000; i++)`
+/// once the loop is rotated:
+///
+/// ```text
+/// loop:
+///   %i = phi i64 [ 0, %entry ], [ %i.next, %loop ]
+///   ...
+///   %i.next = add nuw nsw i64 %i, 1
+///   %done = icmp eq i64 %i.next, 1000
+///   br i1 %done, label %exit, label %loop
+/// ```
+///
+/// The variable is then `0 <= i <= 999` wherever it is available.
+fn _get_induction_range<'a>(header: BasicBlock<'a>, phi: PhiValue<'a>) -> Option<(IntValue<'a>, Range)> {
+
+    let IV(variable) = phi.as_basic_value() else { return None };
+
+    if phi.count_incoming() != 2 {
+        return None;
+    }
+
+    // One incoming value is the constant start, the other the incremented variable
+    let (Some((IV(first), first_bb)), Some((IV(second), second_bb))) = (phi.get_incoming(0), phi.get_incoming(1)) else {
+        return None;
+    };
+
+    let (start, next, latch) = match (first.get_sign_extended_constant(), second.get_sign_extended_constant()) {
+        (Some(start), None) => (start, second, second_bb),
+        (None, Some(start)) => (start, first, first_bb),
+        _ => return None,
+    };
+
+    // %next = add %variable, step
+    let increment = next.as_instruction()?;
+    if increment.get_opcode() != Add {
+        return None;
+    }
+
+    let operand = |i: u32| match increment.get_operand(i).and_then(|operand| operand.left()) {
+        Some(IV(value)) => Some(value),
+        _ => None,
+    };
+
+    let step = match (operand(0)?, operand(1)?) {
+        (lhs, rhs) if lhs == variable => rhs.get_sign_extended_constant()?,
+        (lhs, rhs) if rhs == variable => lhs.get_sign_extended_constant()?,
+        _ => return None,
+    };
+
+    if step == 0 {
+        return None;
+    }
+
+    // The latch branches back to the header while the condition holds (or does not)
+    let terminator = latch.get_terminator()?;
+    if terminator.get_opcode() != Br || terminator.get_num_operands() != 3 {
+        return None;
+    }
+
+    let Some(IV(exit_condition)) = terminator.get_operand(0).and_then(|operand| operand.left()) else {
+        return None;
+    };
+
+    let destination = |i: u32| terminator.get_operand(i).and_then(|operand| operand.right());
+
+    // The false destination is the second operand, the true destination the third,
+    // exactly one of them loops back to the header
+    let taken = match (destination(2) == Some(header), destination(1) == Some(header)) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => return None,
+    };
+
+    let mut conditions = Vec::new();
+    _get_conditions(exit_condition, taken, &mut conditions);
+
+    // Values of the variable without wrapping, computed on 128 bits not to overflow
+    let bit_width = variable.get_type().get_bit_width().min(64);
+    let (type_min, type_max) = (-(1i128 << (bit_width - 1)), (1i128 << (bit_width - 1)) - 1);
+
+    let (start, step) = (start as i128, step as i128);
+
+    let mut range: Option<Range> = None;
+
+    for condition in &conditions {
+
+        let (predicate, lhs, rhs) = *condition;
+
+        // The loop exits when the incremented variable reaches the constant
+        let bounds = if predicate == IntPredicate::NE {
+
+            let constant = match (lhs, rhs) {
+                (lhs, rhs) if lhs == next => rhs.get_sign_extended_constant(),
+                (lhs, rhs) if rhs == next => lhs.get_sign_extended_constant(),
+                _ => None,
+            };
+            let Some(end) = constant.map(|end| end as i128) else { continue };
+
+            if (end - start) % step != 0 {
+                continue;
+            }
+
+            match step > 0 {
+                true if start < end => (start, end - step),
+                false if start > end => (end - step, start),
+                _ => continue,
+            }
+
+        } else {
+
+            // The loop goes on while the incremented variable is in the range
+            let Some((min, max)) = _get_compared_range(condition, next) else { continue };
+            let (min, max) = (min as i128, max as i128);
+
+            // The first value out of the range must not wrap around
+            match step > 0 {
+                true if max < type_max && start.max(max) + step <= type_max => (start, start.max(max)),
+                false if min > type_min && start.min(min) + step >= type_min => (start.min(min), start),
+                _ => continue,
+            }
+
+        };
+
+        let bounds = (bounds.0 as i64, bounds.1 as i64);
+
+        range = Some(match range {
+            Some(known) => intersect(known, bounds),
+            None => bounds,
+        });
+
+    }
+
+    Some((variable, range?))
+
+}
+
+/// Computes the facts known in every block of a function, from the
+/// conditional branches dominating it.
+///
+/// The condition of a branch holds in the blocks dominated by its true
+/// destination, and does not hold in the blocks dominated by its false
+/// destination, as long as the destination has no other predecessor.  The
+/// ranges of the induction variables of loops hold in the blocks dominated by
+/// the header of the loop.
+///
+/// # Arguments
+///
+/// * `function` - The function to analyse.
+///
+/// # Returns
+///
+/// Returns the known facts of each block.  Blocks without facts are not in
+/// the map.
+pub fn get_block_facts<'a>(function: FunctionValue<'a>) -> HashMap<BasicBlock<'a>, Facts<'a>> {
+
+    let blocks: Vec<BasicBlock> = function.get_basic_blocks();
+    let index_of = |bb: BasicBlock| blocks.iter().position(|b| *b == bb);
+
+    // Control flow graph
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); blocks.len()];
+    for (i, bb) in blocks.iter().enumerate() {
+        for successor in _get_successors(*bb) {
+            if let Some(j) = index_of(successor) {
+                predecessors[j].push(i);
+            }
+        }
+    }
+
+    let dominators = _get_dominators(&blocks, &predecessors);
+
+    // Conditions holding in the blocks dominated by a block
+    // (block index, condition)
+    let mut edge_conditions: Vec<(usize, Condition)> = Vec::new();
+
+    for (i, bb) in blocks.iter().enumerate() {
+
+        let Some(terminator) = bb.get_terminator() else { continue };
+
+        // Conditional branch: br i1 %cond, label %true_bb, label %false_bb
+        if terminator.get_opcode() != Br || terminator.get_num_operands() != 3 {
+            continue;
+        }
+
+        let Some(IV(condition)) = terminator.get_operand(0).and_then(|operand| operand.left()) else { continue };
+
+        let destination = |i: u32| terminator.get_operand(i).and_then(|operand| operand.right());
+
+        // Both edges lead to the same block whatever the condition
+        if destination(1) == destination(2) {
+            continue;
+        }
+
+        // The false destination is the second operand, the true destination the third
+        let destinations = [(2, true), (1, false)];
+
+        for (operand_index, taken) in destinations {
+
+            let Some(destination) = destination(operand_index) else { continue };
+            let Some(j) = index_of(destination) else { continue };
+
+            // The edge must be the only way into the destination, predecessors are counted per edge
+            if predecessors[j] != [i] {
+                continue;
+            }
+
+            let mut conditions = Vec::new();
+            _get_conditions(condition, taken, &mut conditions);
+
+            edge_conditions.extend(conditions.into_iter().map(|condition| (j, condition)));
+
+        }
+
+    }
+
+    // Conditions of every dominating edge hold in a block
+    let mut block_facts: HashMap<BasicBlock, Facts> = HashMap::new();
+
+    for (i, bb) in blocks.iter().enumerate() {
+
+        for (j, condition) in &edge_conditions {
+
+            if !dominators[i][*j] {
+                continue;
+            }
+
+            let facts = block_facts.entry(*bb).or_default();
+            facts.conditions.push(*condition);
+
+            if let Some((value, range)) = _get_condition_range(condition) {
+                let known = facts.ranges.get(&value).copied().unwrap_or(FULL_RANGE);
+                facts.ranges.insert(value, intersect(known, range));
+            }
+
+        }
+
+    }
+
+    // Ranges of the induction variables, defined by the phis of loop headers
+    for (j, header) in blocks.iter().enumerate() {
+
+        let phis = header.get_instructions().take_while(|instr| instr.get_opcode() == Phi);
+
+        for phi in phis {
+
+            let Ok(phi) = PhiValue::try_from(phi) else { continue };
+            let Some((variable, range)) = _get_induction_range(*header, phi) else { continue };
+
+            for (i, bb) in blocks.iter().enumerate() {
+
+                if !dominators[i][j] {
+                    continue;
+                }
+
+                let facts = block_facts.entry(*bb).or_default();
+                let known = facts.ranges.get(&variable).copied().unwrap_or(FULL_RANGE);
+                facts.ranges.insert(variable, intersect(known, range));
+
+            }
+
+        }
+
+    }
+
+    block_facts
+
+}
+
+/// Returns the known range of an integer value.
+///
+/// Constants have a single value, the ranges of sign and zero extended
+/// values are derived from the range of the extended value, and values
+/// masked with a non negative constant are between 0 and the mask.
+pub fn get_range(ranges: &Ranges, value: IntValue) -> Range {
+
+    if let Some(constant) = value.get_sign_extended_constant() {
+        return (constant, constant);
+    }
+
+    let known = ranges.get(&value).copied().unwrap_or(FULL_RANGE);
+
+    let Some(instr) = value.as_instruction() else {
+        return known;
+    };
+
+    let Some(IV(extended)) = instr.get_operand(0).and_then(|operand| operand.left()) else {
+        return known;
+    };
+
+    let bit_width = extended.get_type().get_bit_width();
+
+    let range = match instr.get_opcode() {
+
+        // Sign extension keeps the signed value
+        SExt if bit_width < 64 => intersect(get_range(ranges, extended), (-(1 << (bit_width - 1)), (1 << (bit_width - 1)) - 1)),
+        SExt => get_range(ranges, extended),
+
+        // Zero extension keeps non negative values
+        ZExt => match get_range(ranges, extended) {
+            (min, max) if min >= 0 => (min, max),
+            _ if bit_width < 63 => (0, (1 << bit_width) - 1),
+            _ => FULL_RANGE,
+        },
+
+        // Masking with a non negative constant, e.g. `index & 3`, clears the bits above it
+        And => match instr.get_operand(1).and_then(|operand| operand.left()) {
+            Some(IV(mask)) => match mask.get_sign_extended_constant() {
+                Some(mask) if mask >= 0 => (0, mask),
+                _ => FULL_RANGE,
+            },
+            _ => FULL_RANGE,
+        },
+
+        _ => FULL_RANGE,
+
+    };
+
+    intersect(known, range)
+
+}
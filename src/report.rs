@@ -0,0 +1,257 @@
+//! Verification reports, serialisable as JSON and SARIF 2.1.0.
+//!
+//! `static_checks::get_violations` returns every [`Violation`] of a function
+//! instead of a single boolean.  Each violation has a rule ID telling its
+//! kind, and the source location of the offending instruction when the
+//! bitcode has debug info (compiled with `-g`), so that the results can be
+//! uploaded to code scanning dashboards.
+
+use std::path::Path;
+use std::slice;
+
+use inkwell::values::{AsValueRef, InstructionValue};
+use serde::Serialize;
+use serde_json::json;
+
+extern crate llvm_sys as llvm;
+
+/// Name of the tool in SARIF logs.
+const TOOL_NAME: &str = "llvm-sandboxer";
+
+/// Kinds of violations, each with its own rule ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ViolationKind {
+    /// Call to a function that is not allowed by the policy.
+    DisallowedCall,
+    /// Access that cannot be proven inside the protected region.
+    UnprotectedAccess,
+    /// Access proven outside the protected region.
+    OutOfRegionAccess,
+    /// Access whose pointers cannot be computed statically, e.g. a gather.
+    UnanalysableAccess,
+    /// Access through a pointer cast from an integer (`inttoptr`).
+    IntToPtrAccess,
+    /// Call to the begin marker without a pointer and a length.
+    InvalidMarker,
+}
+
+impl ViolationKind {
+
+    /// Every kind of violation.
+    pub const ALL: [ViolationKind; 6] = [
+        ViolationKind::DisallowedCall,
+        ViolationKind::UnprotectedAccess,
+        ViolationKind::OutOfRegionAccess,
+        ViolationKind::UnanalysableAccess,
+        ViolationKind::IntToPtrAccess,
+        ViolationKind::InvalidMarker,
+    ];
+
+    /// Returns the rule ID of the violation kind.
+    pub fn rule_id(&self) -> &'static str {
+        match self {
+            ViolationKind::DisallowedCall => "SBX001",
+            ViolationKind::UnprotectedAccess => "SBX002",
+            ViolationKind::OutOfRegionAccess => "SBX003",
+            ViolationKind::UnanalysableAccess => "SBX004",
+            ViolationKind::IntToPtrAccess => "SBX005",
+            ViolationKind::InvalidMarker => "SBX006",
+        }
+    }
+
+    /// Returns a short description of the rule.
+    pub fn description(&self) -> &'static str {
+        match self {
+            ViolationKind::DisallowedCall => "Call to a function that is not allowed inside a transaction",
+            ViolationKind::UnprotectedAccess => "Memory access that cannot be proven inside the protected region",
+            ViolationKind::OutOfRegionAccess => "Memory access outside the protected region",
+            ViolationKind::UnanalysableAccess => "Memory access that cannot be analysed statically",
+            ViolationKind::IntToPtrAccess => "Memory access through a pointer cast from an integer, whose provenance cannot be verified",
+            ViolationKind::InvalidMarker => "Call to the begin marker that does not take a pointer and a length",
+        }
+    }
+
+}
+
+/// Location of an instruction in the source code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceLocation {
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+/// A violation of the sandbox rules found by the verifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub rule_id: &'static str,
+    pub kind: ViolationKind,
+    /// Name of the function containing the violation.
+    pub function: String,
+    /// The offending instruction, as LLVM IR.
+    pub instruction: String,
+    pub message: String,
+    /// Source location of the instruction, if the bitcode has debug info.
+    pub location: Option<SourceLocation>,
+}
+
+impl Violation {
+
+    /// Creates a violation of `kind` for an instruction.
+    pub fn new(kind: ViolationKind, function: &str, instr: InstructionValue, message: &str) -> Self {
+        Violation {
+            rule_id: kind.rule_id(),
+            kind,
+            function: function.to_string(),
+            instruction: instr.to_string().trim().to_string(),
+            message: message.to_string(),
+            location: get_source_location(instr),
+        }
+    }
+
+}
+
+/// Reads a string returned by the debug location functions of the C API.
+unsafe fn _get_debug_loc_string(
+    value: InstructionValue,
+    get_string: unsafe extern "C" fn(llvm::prelude::LLVMValueRef, *mut u32) -> *const std::ffi::c_char) -> String {
+
+    let mut length: u32 = 0;
+    let string = get_string(value.as_value_ref(), &mut length);
+
+    if string.is_null() {
+        return String::new();
+    }
+
+    String::from_utf8_lossy(slice::from_raw_parts(string as *const u8, length as usize)).to_string()
+
+}
+
+/// Returns the source location of an instruction from its `!dbg` metadata.
+///
+/// # Returns
+///
+/// Returns `None` if the instruction has no debug location.
+pub fn get_source_location(instr: InstructionValue) -> Option<SourceLocation> {
+
+    let line = unsafe { llvm::core::LLVMGetDebugLocLine(instr.as_value_ref()) };
+
+    if line == 0 {
+        return None;
+    }
+
+    let column = unsafe { llvm::core::LLVMGetDebugLocColumn(instr.as_value_ref()) };
+    let file = unsafe { _get_debug_loc_string(instr, llvm::core::LLVMGetDebugLocFilename) };
+    let directory = unsafe { _get_debug_loc_string(instr, llvm::core::LLVMGetDebugLocDirectory) };
+
+    // File names are relative to the compilation directory
+    let file = if directory.is_empty() || Path::new(&file).is_absolute() {
+        file
+    } else {
+        Path::new(&directory).join(&file).display().to_string()
+    };
+
+    Some(SourceLocation { file, line, column })
+
+}
+
+/// Returns the SARIF artifact location of a source file: a `file://` URI if
+/// the path is absolute, otherwise a URI relative to `%SRCROOT%`.
+fn _get_artifact_location(file: &str) -> serde_json::Value {
+
+    // Percent-encode everything but unreserved characters and separators
+    let mut uri = String::new();
+    for byte in file.replace('\\', "/").bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    if !Path::new(file).is_absolute() {
+        return json!({ "uri": uri, "uriBaseId": "%SRCROOT%" });
+    }
+
+    // Windows paths start with the drive, not with a slash
+    let separator = if uri.starts_with('/') { "" } else { "/" };
+    json!({ "uri": format!("file://{}{}", separator, uri) })
+
+}
+
+/// Result of the verification of one or more functions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerificationReport {
+    pub violations: Vec<Violation>,
+}
+
+impl VerificationReport {
+
+    /// Checks if the verification passed, i.e. there are no violations.
+    pub fn passed(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Serialises the report as JSON.
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Failed to serialise report: {}", e))
+    }
+
+    /// Serialises the report as a SARIF 2.1.0 log, with one rule per kind of
+    /// violation and one result per violation.
+    pub fn to_sarif(&self) -> Result<String, String> {
+
+        let rules: Vec<_> = ViolationKind::ALL.iter().map(|kind| json!({
+            "id": kind.rule_id(),
+            "name": kind,
+            "shortDescription": { "text": kind.description() },
+            "defaultConfiguration": { "level": "error" },
+        })).collect();
+
+        let results: Vec<_> = self.violations.iter().map(|violation| {
+
+            let mut result = json!({
+                "ruleId": violation.rule_id,
+                "level": "error",
+                "message": { "text": format!("{}: {}", violation.message, violation.instruction) },
+                "locations": [{
+                    "logicalLocations": [{ "name": violation.function, "kind": "function" }],
+                }],
+            });
+
+            if let Some(location) = &violation.location {
+                result["locations"][0]["physicalLocation"] = json!({
+                    "artifactLocation": _get_artifact_location(&location.file),
+                    "region": { "startLine": location.line },
+                });
+
+                // Column 0 is an unknown column, SARIF columns start at 1
+                if location.column != 0 {
+                    result["locations"][0]["physicalLocation"]["region"]["startColumn"] = json!(location.column);
+                }
+            }
+
+            result
+
+        }).collect();
+
+        let sarif = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": TOOL_NAME,
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    },
+                },
+                "results": results,
+            }],
+        });
+
+        serde_json::to_string_pretty(&sarif).map_err(|e| format!("Failed to serialise report: {}", e))
+
+    }
+
+}
@@ -1,6 +1,6 @@
 //! Adds runtime safeguards to llvm micro-transactions.
 
-use std::str::FromStr;
+use std::collections::HashMap;
 
 // External crates
 use either::*;
@@ -8,108 +8,169 @@ use either::*;
 // Inkwell imports
 use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
-use inkwell::context::Context;
+use inkwell::context::{AsContextRef, Context, ContextRef};
 use inkwell::module::Module;
 use inkwell::values::{BasicValueEnum, GlobalValue, InstructionValue, FunctionValue};
-use inkwell::values::{IntValue, PointerValue, PhiValue};
-use inkwell::values::BasicValueEnum::PointerValue as PV;
+use inkwell::values::{IntValue, PointerValue, PhiValue, VectorValue};
 use inkwell::IntPredicate::*;
-use inkwell::values::AnyValue;
-use inkwell::types::AnyTypeEnum::{ArrayType, FloatType, IntType, PointerType, StructType, VectorType};
-use inkwell::types::BasicTypeEnum;
+use inkwell::values::{AnyValue, AsValueRef, BasicMetadataValueEnum};
+use inkwell::types::FunctionType;
+use inkwell::targets::TargetData;
 
 extern crate llvm_sys as llvm;
 
-use regex::Regex;
-
 // Instruction opcodes
-use inkwell::values::InstructionOpcode::{Call, Load, Store, Phi, Br, Alloca};
+use inkwell::values::InstructionOpcode::{Call, Phi, Br, Alloca};
 
 use crate::static_checks;
+use crate::policy::{self, Policy};
+use crate::ranges::{self, Facts};
+use crate::stats::InstrumentationStats;
+use crate::report;
+
+/// Format of the message printed when a runtime check fails, in diagnostic
+/// mode: site, access size and address, region size and address.
+const VIOLATION_FORMAT: &str = "%s: %llu bytes accessed at %p, outside the region of %llu bytes at %p\n";
+
+/// File descriptor of the standard error.
+const STDERR_FD: u64 = 2;
+
+/// Prefix of the names of the generated blocks, values and globals.  Names
+/// with a dot are not C identifiers, so they cannot collide with the symbols
+/// of the instrumented code.
+const NAME_PREFIX: &str = "__sandbox.";
+
+/// Returns the reserved name of a generated block, value or global.
+fn _reserved_name(name: &str) -> String {
+    format!("{}{}", NAME_PREFIX, name)
+}
+
+/// Named metadata listing the instrumented functions of a module, e.g.
+/// `!llvm_sandboxer.instrumented = !{!0}` with `!0 = !{!"f"}`.
+pub const INSTRUMENTED_METADATA: &str = "llvm_sandboxer.instrumented";
+
+/// Checks if a function of a module has already been instrumented.
+///
+/// # Arguments
+///
+/// * `module` - The LLVM module containing the function.
+/// * `function_name` - The name of the function.
+///
+/// # Returns
+///
+/// Returns `true` if the function is listed in the
+/// [`INSTRUMENTED_METADATA`] of the module, `false` otherwise.
+pub fn is_instrumented(module: &Module, function_name: &str) -> bool {
+
+    module.get_global_metadata(INSTRUMENTED_METADATA).into_iter().any(|node| {
+        node.get_node_values().into_iter().any(|value| match value {
+            BasicMetadataValueEnum::MetadataValue(name) => name.get_string_value().and_then(|name| name.to_str().ok()) == Some(function_name),
+            _ => false,
+        })
+    })
+
+}
+
+/// Records in the module that a function has been instrumented.
+fn _mark_instrumented<'a>(context: &ContextRef<'a>, module: &Module<'a>, function_name: &str) -> Result<(), String> {
+
+    let node = context.metadata_node(&[context.metadata_string(function_name).into()]);
+
+    module.add_global_metadata(INSTRUMENTED_METADATA, &node)
+        .map_err(|e| format!("Failed to mark {} as instrumented: {}", function_name, e))
+
+}
+
+/// Sets the debug location of the instructions built by `builder` to the one
+/// of `instr`, so that generated code is attributed to the original source.
+fn _set_debug_location(builder: &Builder, instr: &InstructionValue) {
+
+    unsafe {
+        let location = llvm::debuginfo::LLVMInstructionGetDebugLoc(instr.as_value_ref());
+        llvm::core::LLVMSetCurrentDebugLocation2(builder.as_mut_ptr(), location);
+    }
+
+}
+
+/// Sets the debug location of the instructions built by `builder` to line 0
+/// of `function`, for code shared by several accesses, if the function has
+/// debug info.
+fn _set_function_debug_location(context: &ContextRef, builder: &Builder, function: &FunctionValue) {
+
+    unsafe {
+        let subprogram = llvm::debuginfo::LLVMGetSubprogram(function.as_value_ref());
+        if subprogram.is_null() {
+            return;
+        }
+        let location = llvm::debuginfo::LLVMDIBuilderCreateDebugLocation(
+            context.as_ctx_ref(), 0, 0, subprogram, std::ptr::null_mut());
+        llvm::core::LLVMSetCurrentDebugLocation2(builder.as_mut_ptr(), location);
+    }
+
+}
+
+/// Moves an instruction to the position of `builder`, keeping its debug location.
+fn _move_instruction<'a>(builder: &Builder<'a>, instr: InstructionValue<'a>) {
+
+    // Inserting through a builder may overwrite the debug location
+    let location = unsafe { llvm::debuginfo::LLVMInstructionGetDebugLoc(instr.as_value_ref()) };
+
+    instr.remove_from_basic_block();
+    builder.insert_instruction(&instr, None);
+
+    unsafe { llvm::debuginfo::LLVMInstructionSetDebugLoc(instr.as_value_ref(), location) };
+
+}
 
 /// Moves an instruction `instr` and the following ones to a new block `to_block`
-fn _move_instructions(context: &Context, instr: &InstructionValue, to_block: &BasicBlock) {
+fn _move_instructions(context: &ContextRef, instr: &InstructionValue, to_block: &BasicBlock) {
 
     let builder = context.create_builder();
     builder.position_at_end(*to_block);
 
     let mut current_instr = *instr;
     while let Some(next_instr) = current_instr.get_next_instruction() {
-        current_instr.remove_from_basic_block();
-        builder.insert_instruction(&current_instr, None);
+        _move_instruction(&builder, current_instr);
         current_instr = next_instr;
     }
 
     // Last instruction
-    current_instr.remove_from_basic_block();
-    builder.insert_instruction(&current_instr, None);
+    _move_instruction(&builder, current_instr);
 
 }
 
-fn _build_check(
-    context: &Context,
-    builder: Builder,
-    protected_mem: (GlobalValue, GlobalValue),
-    accessed_mem: (PointerValue, IntValue),
-    abort_block: &BasicBlock,
-    continue_block: BasicBlock,
+/// Builds the computation of a boolean that is true when `accessed_mem`
+/// (pointer, size) is not inside `bounds` (pointer, size).
+fn _build_bounds_violation<'a>(
+    context: &ContextRef<'a>,
+    builder: &Builder<'a>,
+    bounds: (PointerValue<'a>, IntValue<'a>),
+    accessed_mem: (PointerValue<'a>, IntValue<'a>),
     block_name: &str
-    ) -> Result<(), String> {
+    ) -> Result<IntValue<'a>, String> {
 
     let i64_type = context.i64_type();
-    let ptr_type = context.i8_type().ptr_type(inkwell::AddressSpace::default());
 
     // Unpacking
-    let protected_ptr: GlobalValue<'_> = protected_mem.0;
-    let protected_offset: GlobalValue<'_> = protected_mem.1;
+    let (protected_ptr_val, protected_offset_val) = bounds;
     let accessed_ptr_val: PointerValue<'_> = accessed_mem.0;
     let alignment_as_int_value: IntValue<'_> = accessed_mem.1;
 
-    // Load pointer value from @protected_ptr
-    let protected_ptr_val: PointerValue = match builder.build_load(
-        ptr_type,
-        protected_ptr.as_pointer_value(),
-        &format!("protected_ptr_{}", block_name),) {
-        Ok(value) => value.into_pointer_value(),
-        Err(_) => return Err("Failed to build load from @protected_val".to_string()),
-    };
-
-    // Check if @protected_ptr is null
-    let null_ptr = context.i8_type().ptr_type(inkwell::AddressSpace::default()).const_null();
-    let protected_is_null = match builder.build_int_compare(
-        EQ, 
-        protected_ptr_val, 
-        null_ptr,
-        &format!("protected_is_null_{}", block_name)) {
-        Ok(value) => value,
-        Err(_) => return Err("Failed to build check for null protected pointer".to_string())
-    };
-
     // Compare accessed pointer value with protected pointer value
     let accessed_lt_protected = match builder.build_int_compare(
         SLT, 
         accessed_ptr_val, 
         protected_ptr_val, 
-        &format!("accessed_lt_protected_{}", block_name)) {
+        &_reserved_name(&format!("accessed_lt_protected_{}", block_name))) {
         Ok(value) => value,
         Err(_) => return Err("Failed to build integer comparison for 'accessed_ptr_val' < 'protected_ptr_val'".to_string())
     };
 
-    // Load protected offset value
-    let protected_offset_val = match builder.build_load(
-        i64_type,
-        protected_offset.as_pointer_value(),
-        &format!("protected_offset_{}", block_name),
-        ) {
-        Ok(value) => value.into_int_value(),
-        Err(_) => return Err("Failed to load value for 'protected_offset_val'".to_string()),
-    };
-
     // Convert protected pointer to int to compute the last protected pointer
     let protected_ptr_val_as_int = match builder.build_ptr_to_int(
         protected_ptr_val, 
         i64_type, 
-        &format!("protected_ptr_as_int{}", block_name)) {
+        &_reserved_name(&format!("protected_ptr_as_int{}", block_name))) {
         Ok(value) => value,
         Err(_) => return Err("Failed to convert @protected_ptr to int".to_string()),
     };
@@ -118,7 +179,7 @@ fn _build_check(
     let last_protected_ptr_val = match builder.build_int_add(
         protected_ptr_val_as_int,
         protected_offset_val, 
-        &format!("last_protected_ptr_{}", block_name)) {
+        &_reserved_name(&format!("last_protected_ptr_{}", block_name))) {
         Ok(value) => value,
         Err(_) => return Err("Failed to build last_protected_ptr_val calculation.".to_string()),
     };
@@ -127,7 +188,7 @@ fn _build_check(
     let accessed_ptr_val_as_int = match builder.build_ptr_to_int(
         accessed_ptr_val, 
         i64_type, 
-        &format!("accessed_ptr_as_int{}", block_name)) {
+        &_reserved_name(&format!("accessed_ptr_as_int{}", block_name))) {
         Ok(value) => value,
         Err(_) => return Err("Failed to cast pointer to int".to_string()),
     };
@@ -136,7 +197,7 @@ fn _build_check(
     let last_accessed_ptr_val_as_int = match builder.build_int_add(
         accessed_ptr_val_as_int,
         alignment_as_int_value, 
-        &format!("last_accessed_ptr_as_int{}", block_name)) {
+        &_reserved_name(&format!("last_accessed_ptr_as_int{}", block_name))) {
         Ok(value) => value,
         Err(_) => return Err("Failed to build last_protected_ptr_val calculation.".to_string()),
     };
@@ -146,29 +207,107 @@ fn _build_check(
         SGT, 
         last_accessed_ptr_val_as_int, 
         last_protected_ptr_val,
-        &format!("last_acc_gt_last_prot_{}", block_name)) {
+        &_reserved_name(&format!("last_acc_gt_last_prot_{}", block_name))) {
             Ok(value) => value,
             Err(_) => return Err("Failed to build integer comparison for 'last_accessed_ptr_val' > 'last_protected_ptr_val'".to_string())
     };
 
     // Build logical OR operation for checks
-    let check_range = match builder.build_or(
+    match builder.build_or(
         accessed_lt_protected, 
         last_acc_gt_last_prot, 
-        &format!("check_range_{}", block_name)) {
-            Ok(value) => value,
-            Err(_) => return Err("Failed to build logical OR operation for 'accessed_lt_protected' || 'last_acc_gt_last_prot'".to_string())
+        &_reserved_name(&format!("check_range_{}", block_name))) {
+            Ok(value) => Ok(value),
+            Err(_) => Err("Failed to build logical OR operation for 'accessed_lt_protected' || 'last_acc_gt_last_prot'".to_string())
+    }
+
+}
+
+/// Builds the computation of a boolean that is true when `accessed_mem`
+/// (pointer, size) is not inside the protected memory.
+fn _build_range_violation<'a>(
+    context: &ContextRef<'a>,
+    builder: &Builder<'a>,
+    protected_mem: (GlobalValue<'a>, GlobalValue<'a>),
+    accessed_mem: (PointerValue<'a>, IntValue<'a>),
+    block_name: &str
+    ) -> Result<IntValue<'a>, String> {
+
+    let i64_type = context.i64_type();
+    let ptr_type = context.i8_type().ptr_type(inkwell::AddressSpace::default());
+
+    // Unpacking
+    let protected_ptr: GlobalValue<'_> = protected_mem.0;
+    let protected_offset: GlobalValue<'_> = protected_mem.1;
+
+    // Load pointer value from @protected_ptr
+    let protected_ptr_val: PointerValue = match builder.build_load(
+        ptr_type,
+        protected_ptr.as_pointer_value(),
+        &_reserved_name(&format!("protected_ptr_{}", block_name)),) {
+        Ok(value) => value.into_pointer_value(),
+        Err(_) => return Err("Failed to build load from @protected_val".to_string()),
+    };
+
+    // Check if @protected_ptr is null
+    let null_ptr = context.i8_type().ptr_type(inkwell::AddressSpace::default()).const_null();
+    let protected_is_null = match builder.build_int_compare(
+        EQ, 
+        protected_ptr_val, 
+        null_ptr,
+        &_reserved_name(&format!("protected_is_null_{}", block_name))) {
+        Ok(value) => value,
+        Err(_) => return Err("Failed to build check for null protected pointer".to_string())
+    };
+
+    // Load protected offset value
+    let protected_offset_val = match builder.build_load(
+        i64_type,
+        protected_offset.as_pointer_value(),
+        &_reserved_name(&format!("protected_offset_{}", block_name)),
+        ) {
+        Ok(value) => value.into_int_value(),
+        Err(_) => return Err("Failed to load value for 'protected_offset_val'".to_string()),
     };
 
+    let check_range = _build_bounds_violation(
+        context,
+        builder,
+        (protected_ptr_val, protected_offset_val),
+        accessed_mem,
+        block_name)?;
+
     // Build logical OR operation for checks
     let check = match builder.build_or(
         protected_is_null, 
         check_range, 
-        &format!("check_{}", block_name)) {
+        &_reserved_name(&format!("check_{}", block_name))) {
             Ok(value) => value,
             Err(_) => return Err("Failed to build logical OR operation for 'accessed_lt_protected' || 'last_acc_gt_last_prot'".to_string())
     };
 
+    Ok(check)
+
+}
+
+/// Builds the check of `accessed_mem` (pointer, size) against the protected
+/// memory, or against `stack_bounds` (pointer, size) if given.
+fn _build_check(
+    context: &ContextRef,
+    builder: Builder,
+    protected_mem: (GlobalValue, GlobalValue),
+    accessed_mem: (PointerValue, IntValue),
+    stack_bounds: Option<(PointerValue, IntValue)>,
+    abort_block: &BasicBlock,
+    continue_block: BasicBlock,
+    block_name: &str
+    ) -> Result<(), String> {
+
+    let check = match stack_bounds {
+        Some(bounds) => _build_bounds_violation(context, &builder, bounds, accessed_mem, block_name)?,
+        None => _build_range_violation(context, &builder, protected_mem, accessed_mem, block_name)?,
+    };
+
     // Create the instruction that evaluates comparison and chooses to abort or continue
     match builder.build_conditional_branch(check, *abort_block, continue_block) {
         Ok(_) => Ok(()),
@@ -177,128 +316,260 @@ fn _build_check(
 
 }
 
+/// Builds the check of a vector of pointers `ptrs`, each one accessing
+/// `element_size` bytes, for the lanes enabled by `mask`.
+///
+/// If `report_lane` is set, returns the pointer of a lane violating the
+/// protected memory, to report it.
+fn _build_lanes_check<'a>(
+    context: &ContextRef<'a>,
+    builder: Builder<'a>,
+    protected_mem: (GlobalValue<'a>, GlobalValue<'a>),
+    accessed_lanes: (VectorValue<'a>, VectorValue<'a>, u64),
+    abort_block: &BasicBlock<'a>,
+    continue_block: BasicBlock<'a>,
+    block_name: &str,
+    report_lane: bool
+    ) -> Result<Option<PointerValue<'a>>, String> {
 
-/// Extracts entries of a phi instruction.
-fn _get_phi_entries<'a>(instr: &'a InstructionValue<'a>) -> Vec<(BasicValueEnum<'a>, String)> {
+    let i64_type = context.i64_type();
 
-    if instr.get_opcode() != Phi {
-        panic!("Instruction is not Phi!");
-    }
+    // Unpacking
+    let (ptrs, mask, element_size) = accessed_lanes;
+    let element_size_as_int_value: IntValue<'_> = i64_type.const_int(element_size, false);
+
+    // No lane violates the protected memory so far
+    let mut check: IntValue<'_> = context.bool_type().const_zero();
+    let mut violating_ptr: Option<PointerValue> = None;
 
-    let instr_as_llvmstring: inkwell::support::LLVMString = instr.print_to_string();
-    let instr_as_str: &str = instr_as_llvmstring.to_str().expect("Failed to convert LLVMString to &str.");
+    for lane in 0..ptrs.get_type().get_size() {
 
-    // To store the Phi entries
-    let mut entries: Vec<(BasicValueEnum, String)> = Vec::new();
+        let lane_index = i64_type.const_int(lane as u64, false);
+        let lane_name = format!("{}_lane{}", block_name, lane);
+
+        // Extract the pointer accessed by the lane
+        let lane_ptr: PointerValue = match builder.build_extract_element(ptrs, lane_index, &_reserved_name(&format!("ptr_{}", lane_name))) {
+            Ok(value) => value.into_pointer_value(),
+            Err(_) => return Err(format!("Failed to extract pointer of lane {}", lane)),
+        };
 
-    // Define a regular expression pattern to match phi entries e.g. [ %1, %0 ]
-    let entry_pattern: Regex = Regex::new(r" %?(\w)+, %(\w)+").unwrap();
+        // Extract the mask of the lane
+        let lane_enabled: IntValue = match builder.build_extract_element(mask, lane_index, &_reserved_name(&format!("mask_{}", lane_name))) {
+            Ok(value) => value.into_int_value(),
+            Err(_) => return Err(format!("Failed to extract mask of lane {}", lane)),
+        };
 
-    for (i, capture) in entry_pattern.captures_iter(instr_as_str).enumerate() {
+        let lane_violation = _build_range_violation(
+            context,
+            &builder,
+            protected_mem,
+            (lane_ptr, element_size_as_int_value),
+            &lane_name)?;
 
-        let binding: String = capture[0].to_string();
-        let mut parts: std::str::Split<'_, char> = binding.split(',');
+        // Disabled lanes do not access memory
+        let lane_check = match builder.build_and(lane_violation, lane_enabled, &_reserved_name(&format!("check_{}", lane_name))) {
+            Ok(value) => value,
+            Err(_) => return Err(format!("Failed to build logical AND operation for lane {}", lane)),
+        };
 
-        // If the pattern matches every entry, then we can retrieve the value with the "get_operand()" method
-        let value: BasicValueEnum<'_> = instr.get_operand(i as u32)
-        .expect("Failed to get value from phi instruction. Reason: Operand not found.")
-        .left()
-        .expect("Expected BasicValueEnum, found BasicBlock.");
+        check = match builder.build_or(check, lane_check, &_reserved_name(&format!("check_{}_lanes{}", block_name, lane))) {
+            Ok(value) => value,
+            Err(_) => return Err(format!("Failed to build logical OR operation for lane {}", lane)),
+        };
 
-        let bb_name: &str = parts.nth(1)
-        .expect("Failed to get the BasicBlock name from capture. Reason: The 'parts' iterator does not contain a second element.");
+        // Keep the pointer of the last violating lane
+        if report_lane {
+            violating_ptr = Some(match violating_ptr {
+                Some(previous_ptr) => builder.build_select(lane_check, lane_ptr, previous_ptr, &_reserved_name(&format!("violating_ptr_{}", lane_name)))
+                    .map_err(|e| format!("Failed to select violating pointer of lane {}: {:?}", lane, e))?
+                    .into_pointer_value(),
+                None => lane_ptr,
+            });
+        }
 
-        entries.push((
-            value,
-            bb_name.to_string().replace(" %", "")));    // Clean basic block name
     }
 
-    entries
+    // Create the instruction that evaluates comparison and chooses to abort or continue
+    match builder.build_conditional_branch(check, *abort_block, continue_block) {
+        Ok(_) => Ok(violating_ptr),
+        Err(e) => Err(format!("Failed to build conditional branch: {:?}", e))
+    }
 
 }
 
-/// Updates a phi instruction
-fn _update_phi(
-    context: &Context, 
-    function: &FunctionValue,
-    phi_bb: BasicBlock,
-    instr: &InstructionValue, 
-    previous_bb_name: &str, 
-    continue_block: &BasicBlock,
-    phi_counter: &mut u32) {
+/// Builds the report of a violation of `accessed_mem` (pointer, size) on the
+/// standard error, followed by a branch to `abort_block`.
+///
+/// The reported region is `bounds` (pointer, size) if given, e.g. a stack
+/// allocation, and the protected memory otherwise.
+fn _build_violation_report<'a>(
+    context: &ContextRef<'a>,
+    builder: &Builder<'a>,
+    diagnostic: (FunctionValue<'a>, GlobalValue<'a>, &str),
+    accessed_mem: (PointerValue<'a>, IntValue<'a>),
+    bounds: Option<(PointerValue<'a>, IntValue<'a>)>,
+    protected_mem: (GlobalValue<'a>, GlobalValue<'a>),
+    abort_block: &BasicBlock<'a>
+    ) -> Result<(), String> {
 
-    if instr.get_opcode() != Phi {
-        panic!("Instruction is not Phi!");
+    let i64_type = context.i64_type();
+    let ptr_type = context.i8_type().ptr_type(inkwell::AddressSpace::default());
+
+    // Unpacking
+    let (print_function, format, site) = diagnostic;
+    let (accessed_ptr_val, size_as_int_value) = accessed_mem;
+
+    // Region active when the check failed
+    let (region_ptr_val, region_len_val) = match bounds {
+        Some(bounds) => bounds,
+        None => {
+            let region_ptr_val = builder.build_load(ptr_type, protected_mem.0.as_pointer_value(), &_reserved_name("violation_region_ptr"))
+                .map_err(|e| format!("Failed to load protected pointer value: {:?}", e))?
+                .into_pointer_value();
+            let region_len_val = builder.build_load(i64_type, protected_mem.1.as_pointer_value(), &_reserved_name("violation_region_len"))
+                .map_err(|e| format!("Failed to load protected offset value: {:?}", e))?
+                .into_int_value();
+            (region_ptr_val, region_len_val)
+        }
+    };
+
+    // Description of the site, e.g. `file.c:12:5: store in f`
+    let site = builder.build_global_string_ptr(site, &_reserved_name("violation_site"))
+        .map_err(|e| format!("Failed to build violation site string: {:?}", e))?;
+
+    builder.build_call(
+        print_function,
+        &[
+            context.i32_type().const_int(STDERR_FD, false).into(),
+            format.as_pointer_value().into(),
+            site.as_pointer_value().into(),
+            size_as_int_value.into(),
+            accessed_ptr_val.into(),
+            region_len_val.into(),
+            region_ptr_val.into(),
+        ],
+        "")
+        .map_err(|e| format!("Failed to build violation report: {:?}", e))?;
+
+    match builder.build_unconditional_branch(*abort_block) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(format!("Failed to build branch to abort block: {:?}", e))
     }
 
-    // Get entries of Phi instruction
-    let entries: Vec<(BasicValueEnum, String)> = _get_phi_entries(instr);
+}
 
-    let mut bb_names: Vec<String> = Vec::new(); 
+/// Returns the description of an access site used in violation reports:
+/// the source location if known, the access kind and the function.
+fn _get_site_description(function_name: &str, kind: &str, instr: InstructionValue) -> String {
 
-    // Extract basic block names
-    for entry in &entries {
-        bb_names.push(entry.1.clone());
+    match report::get_source_location(instr) {
+        Some(location) => format!("{}:{}:{}: {} in {}", location.file, location.line, location.column, kind, function_name),
+        None => format!("{} in {}", kind, function_name),
     }
 
-    // If one of the labels is equal to the previous one, then update the instruction
-    let previous_bb_name_as_string = String::from_str(previous_bb_name)
-    .expect("Failed to convert previous_bb_name to String.");
-    if bb_names.contains(&previous_bb_name_as_string) {
+}
 
-        // Create builder for new Phi instruction
-        let builder = context.create_builder();
-        builder.position_at(phi_bb, instr);
+/// Returns the function `name` of the module, declaring it with type
+/// `fn_type` if the module does not declare it.
+///
+/// An existing declaration, e.g. `abort` declared by `stdlib.h`, is reused
+/// if `is_compatible` accepts its type, and is an error otherwise.
+fn _get_or_declare_function<'a>(
+    module: &Module<'a>,
+    name: &str,
+    fn_type: FunctionType<'a>,
+    is_compatible: impl Fn(FunctionType<'a>) -> bool
+    ) -> Result<FunctionValue<'a>, String> {
 
-        // Extract phi type
-        let phi_type = match instr.get_type() {
-            ArrayType(t) => BasicTypeEnum::ArrayType(t),
-            FloatType(t) => BasicTypeEnum::FloatType(t),
-            IntType(t) => BasicTypeEnum::IntType(t),
-            PointerType(t) => BasicTypeEnum::PointerType(t),
-            StructType(t) => BasicTypeEnum::StructType(t),
-            VectorType(t) => BasicTypeEnum::VectorType(t),
-            other_type => panic!("Expected BasicType, found {}", other_type),
-        };
+    if let Some(function) = module.get_function(name) {
 
-        let phi_name = format!("phi{}", phi_counter);
-        *phi_counter += 1;
-        let new_phi: PhiValue = builder.build_phi(phi_type, &phi_name)
-        .expect("Failed to build phi value.");
+        if !is_compatible(function.get_type()) {
+            return Err(format!("Function {} is declared with type {}, expected {}",
+                name, function.get_type().print_to_string(), fn_type.print_to_string()));
+        }
 
-        // Iterate over the entries of the old phi instructions to build the new one
-        for entry in entries {
+        return Ok(function);
+
+    }
 
-            // Unpack entry
-            let entry_value: BasicValueEnum<'_> = entry.0;
-            let entry_bb_name: String= entry.1.clone();
+    // A declaration would be renamed to avoid the global
+    if module.get_global(name).is_some() {
+        return Err(format!("{} is a global variable, expected a function of type {}", name, fn_type.print_to_string()));
+    }
 
-            // If the entry reference is equal to the previous reference then change it with the new block
-            if entry_bb_name == *previous_bb_name {
+    Ok(module.add_function(name, fn_type, None))
 
-                new_phi.add_incoming(&[(&entry_value, *continue_block)]);
+}
 
-            } else {    // else put the entry back into the new phi
+/// Returns the function called when a runtime check fails, of type
+/// `void ()`, declaring it if the module does not.
+///
+/// A variadic `void (...)` declaration is also accepted, since it can be
+/// called without arguments.
+fn _get_violation_handler<'a>(context: &ContextRef<'a>, module: &Module<'a>, name: &str) -> Result<FunctionValue<'a>, String> {
 
-                // Find the basic block with the entry_bb_reference
-                for bb in function.get_basic_block_iter() {
+    let handler_type = context.void_type().fn_type(&[], false);
 
-                    let bb_name = bb.get_name().to_str().unwrap(); 
+    _get_or_declare_function(module, name, handler_type, |fn_type| {
+        fn_type.get_return_type().is_none() && fn_type.count_param_types() == 0
+    })
 
-                    if bb_name == entry_bb_name {
+}
 
-                            new_phi.add_incoming(&[(&entry_value, bb)]);
+/// Returns the function printing violation reports, `dprintf`, declaring it
+/// if the module does not.
+fn _get_print_function<'a>(context: &ContextRef<'a>, module: &Module<'a>) -> Result<FunctionValue<'a>, String> {
 
-                    }
+    let ptr_type = context.i8_type().ptr_type(inkwell::AddressSpace::default());
+    let print_type = context.i32_type().fn_type(&[context.i32_type().into(), ptr_type.into()], true);
 
-                }
+    _get_or_declare_function(module, "dprintf", print_type, |fn_type| fn_type == print_type)
 
-            }
+}
+
+/// Updates a phi instruction
+///
+/// Incoming entries from `previous_bb` now come from `continue_block`.  The
+/// phi is rebuilt with the new entries and takes the name of the old one.
+fn _update_phi(
+    context: &ContextRef, 
+    phi_bb: BasicBlock,
+    instr: &InstructionValue, 
+    previous_bb: &BasicBlock, 
+    continue_block: &BasicBlock) {
+
+    let phi: PhiValue = PhiValue::try_from(*instr).expect("Instruction is not Phi!");
+
+    // Get entries of Phi instruction
+    let entries: Vec<(BasicValueEnum, BasicBlock)> = phi.get_incomings().collect();
+
+    // If one of the entries comes from the previous block, then update the instruction
+    if entries.iter().any(|(_, entry_bb)| entry_bb == previous_bb) {
+
+        // Create builder for new Phi instruction
+        let builder = context.create_builder();
+        builder.position_at(phi_bb, instr);
+        _set_debug_location(&builder, instr);
+
+        let phi_name = phi.get_name().to_str().unwrap_or_default().to_string();
+        let new_phi: PhiValue = builder.build_phi(phi.as_basic_value().get_type(), &phi_name)
+        .expect("Failed to build phi value.");
+
+        // Iterate over the entries of the old phi instructions to build the new one
+        for (entry_value, entry_bb) in entries {
+
+            // If the entry comes from the previous block then change it with the new block
+            let entry_bb = if entry_bb == *previous_bb { *continue_block } else { entry_bb };
+
+            new_phi.add_incoming(&[(&entry_value, entry_bb)]);
 
         }
 
         instr.replace_all_uses_with(&new_phi.as_instruction());
         instr.erase_from_basic_block();
+
+        // The name is free once the old phi is erased
+        new_phi.set_name(&phi_name);
             
     }
 
@@ -306,18 +577,16 @@ fn _update_phi(
 
 /// Finds updates all phi instructions in the basic block bb
 fn _update_phi_in_branch(
-    context: &Context, 
-    function: &FunctionValue, 
+    context: &ContextRef, 
     bb: &BasicBlock, 
-    previous_bb_name: &str, 
-    new_bb: &BasicBlock, 
-    phi_counter: &mut u32) {
+    previous_bb: &BasicBlock, 
+    new_bb: &BasicBlock) {
 
     for instr in bb.get_instructions() {
 
         if instr.get_opcode() == Phi {
 
-            _update_phi(context, function,*bb, &instr, previous_bb_name, new_bb, phi_counter);
+            _update_phi(context, *bb, &instr, previous_bb, new_bb);
 
         }
 
@@ -333,11 +602,9 @@ fn _update_phi_in_branch(
 /// predecessors.
 // TODO: Build tests for this function
 fn _check_phi(
-    context: &Context, 
-    function: &FunctionValue, 
+    context: &ContextRef, 
     continue_block: &BasicBlock, 
-    previous_bb_name: &str, 
-    phi_counter: &mut u32) {
+    previous_bb: &BasicBlock) {
 
     // Look for branch instructions
     for instr in continue_block.get_instructions() {
@@ -352,7 +619,7 @@ fn _check_phi(
                 .right()
                 .expect("Expected BasicBlock, found BasicValueEnum.");
 
-                _update_phi_in_branch(context, function, &bb, previous_bb_name, continue_block, phi_counter);
+                _update_phi_in_branch(context, &bb, previous_bb, continue_block);
                 
             } else {    // Conditional branch
 
@@ -362,7 +629,7 @@ fn _check_phi(
                 .right()
                 .expect("Expected BasicBlock, found BasicValueEnum.");
 
-                _update_phi_in_branch(context, function, &bb_1, previous_bb_name, continue_block, phi_counter);
+                _update_phi_in_branch(context, &bb_1, previous_bb, continue_block);
 
                 // Look for phi instructions in the second target blocks
                 let bb_2: BasicBlock<'_> = instr.get_operand(2)
@@ -370,7 +637,7 @@ fn _check_phi(
                 .right()
                 .expect("Expected BasicBlock, found BasicValueEnum.");
 
-                _update_phi_in_branch(context, function, &bb_2, previous_bb_name, continue_block, phi_counter);
+                _update_phi_in_branch(context, &bb_2, previous_bb, continue_block);
 
             }
 
@@ -380,48 +647,263 @@ fn _check_phi(
 
 }
 
-fn _handle_store_or_load(
-    context: &Context, 
-    function: &FunctionValue,
-    instr: &InstructionValue, 
-    protected_mem: (GlobalValue, GlobalValue),
-    abort_bb: &BasicBlock,
-    prev_bb: &BasicBlock, 
+/// Checks if an accessed pointer is a stack slot, i.e. an `alloca`, directly.
+///
+/// Only the pointer operand matters: storing the address of a stack slot
+/// through another pointer writes outside the stack.
+fn _is_stack_slot(ptr: PointerValue) -> bool {
+
+    ptr.as_instruction().map(|instr| instr.get_opcode()) == Some(Alloca)
+
+}
+
+/// Builds the bounds (pointer, size) of a stack allocation `alloca`
+/// (alloca, allocated type size in bytes).
+fn _build_alloca_bounds<'a>(
+    context: &ContextRef<'a>,
+    builder: &Builder<'a>,
+    alloca: (InstructionValue<'a>, u64)
+    ) -> Result<(PointerValue<'a>, IntValue<'a>), String> {
+
+    let i64_type = context.i64_type();
+
+    // Unpacking
+    let (alloca_instr, type_size) = alloca;
+
+    let alloca_ptr = PointerValue::try_from(alloca_instr)
+        .map_err(|_| "Failed to convert alloca to pointer value".to_string())?;
+
+    // Number of allocated elements, not constant for variable length arrays
+    let count: IntValue = match alloca_instr.get_operand(0) {
+        Some(Left(BasicValueEnum::IntValue(count))) => count,
+        _ => return Err("Failed to extract alloca element count".to_string()),
+    };
+
+    if let Some(count) = count.get_zero_extended_constant() {
+        return Ok((alloca_ptr, i64_type.const_int(count * type_size, false)));
+    }
+
+    let count_as_i64 = builder.build_int_z_extend_or_bit_cast(count, i64_type, &_reserved_name("alloca_count"))
+        .map_err(|e| format!("Failed to extend alloca element count: {:?}", e))?;
+
+    let size = builder.build_int_mul(count_as_i64, i64_type.const_int(type_size, false), &_reserved_name("alloca_size"))
+        .map_err(|e| format!("Failed to compute alloca size: {:?}", e))?;
+
+    Ok((alloca_ptr, size))
+
+}
+
+/// How a memory access is handled by the instrumentation.
+enum AccessHandling<'a> {
+    /// Exempt from checks by the policy.
+    Exempt,
+    /// Access to a stack slot, not checked.
+    Stack,
+    /// Proven inside the protected region, not checked.
+    Elided,
+    /// Checked at runtime, against the bounds of a stack allocation (alloca,
+    /// allocated type size in bytes) if given, else the protected region.
+    Checked(Option<(InstructionValue<'a>, u64)>),
+}
+
+/// Decides how each memory access of a function is handled.
+///
+/// The decisions are taken before the function is modified: splitting a
+/// block rebuilds the phis of its successors, and the facts on the indices
+/// (see [`ranges::get_block_facts`]) would refer to the erased ones.
+fn _get_access_handlings<'a>(
+    module: &Module<'a>,
+    function: FunctionValue<'a>,
+    policy: &Policy,
+    target_data: &TargetData) -> Result<HashMap<InstructionValue<'a>, AccessHandling<'a>>, String> {
+
+    // Facts on the indices known from the branch conditions
+    let block_facts = ranges::get_block_facts(function);
+    let no_facts = Facts::default();
+
+    // Region protected by the last `utx1` call, for static analysis
+    let mut protected_mem_static: (Option<PointerValue>, Option<IntValue>) = (None, None);
+
+    let mut handlings: HashMap<InstructionValue, AccessHandling> = HashMap::new();
+
+    for basic_block in function.get_basic_blocks() {
+
+        let facts = block_facts.get(&basic_block).unwrap_or(&no_facts);
+
+        for instr in basic_block.get_instructions() {
+
+            if policy.is_begin_marker(instr) && !policy.is_end_marker(instr) {
+                if policy.static_analysis {
+                    let (ptr, offset) = static_checks::handle_utx1(instr)?;
+                    protected_mem_static = (Some(ptr), Some(offset));
+                }
+                continue;
+            }
+
+            // Kind of memory access, if the instruction accesses memory
+            let Some(kind) = static_checks::get_access_kind(instr) else {
+                continue;
+            };
+
+            // Gathers and scatters are always checked against the protected region
+            let Some((ptr, size)) = static_checks::get_accessed_memory(target_data, instr) else {
+                handlings.insert(instr, AccessHandling::Checked(None));
+                continue;
+            };
+
+            if static_checks::is_exempt_access(target_data, policy, ptr, size, kind, facts) {
+                handlings.insert(instr, AccessHandling::Exempt);
+                continue;
+            }
+
+            // Stack allocation accessed out of its proven bounds, to check against its bounds
+            let mut stack_alloca: Option<(InstructionValue, u64)> = None;
+
+            if policy.check_stack_bounds {
+                if let Some((alloca, constant_offset)) = static_checks::get_stack_base(ptr) {
+
+                    // Stack slots at a constant offset are exempt, if the offset is inside the allocation
+                    if constant_offset && static_checks::is_stack_access_in_bounds(target_data, alloca, ptr, size, facts) {
+                        handlings.insert(instr, AccessHandling::Stack);
+                        continue;
+                    }
+
+                    let allocated_type = alloca.get_allocated_type()
+                        .map_err(|e| format!("Failed to get allocated type: {}", e))?;
+                    stack_alloca = Some((alloca, target_data.get_abi_size(&allocated_type)));
+
+                }
+            }
+
+            // If it's stack skip
+            if stack_alloca.is_none() && _is_stack_slot(ptr) {
+                handlings.insert(instr, AccessHandling::Stack);
+                continue;
+            }
+
+            // Skip the accesses proven safe, pointers cast from integers
+            // are never proven safe and always keep their check
+            if policy.static_analysis && stack_alloca.is_none()
+                && static_checks::is_address_protected(module.clone(), &protected_mem_static, ptr, size, facts) {
+                handlings.insert(instr, AccessHandling::Elided);
+                continue;
+            }
+
+            handlings.insert(instr, AccessHandling::Checked(stack_alloca));
+
+        }
+
+    }
+
+    Ok(handlings)
+
+}
+
+/// Adds a runtime check before a memory access instruction `instr`, which
+/// accesses `accessed_mem`: either a range (pointer, size in bytes) or the
+/// lanes of a gather or scatter (pointers, mask, element size in bytes).
+///
+/// If `stack_alloca` (alloca, allocated type size in bytes) is given, the
+/// range is checked against the stack allocation instead of the protected
+/// memory.
+///
+/// If `diagnostic` (print function, format string, site description) is
+/// given, a failed check reports the violation before aborting.
+fn _handle_memory_access<'a>(
+    context: &ContextRef<'a>, 
+    instr: &InstructionValue<'a>, 
+    accessed_mem: Either<(PointerValue<'a>, u64), (VectorValue<'a>, VectorValue<'a>, u64)>,
+    stack_alloca: Option<(InstructionValue<'a>, u64)>,
+    protected_mem: (GlobalValue<'a>, GlobalValue<'a>),
+    diagnostic: Option<(FunctionValue<'a>, GlobalValue<'a>, &str)>,
+    abort_bb: &BasicBlock<'a>,
+    prev_bb: &BasicBlock<'a>, 
     new_bb_name: &str,
-    current_block_name: &mut String,
-    phi_counter: &mut u32) -> Result<(), String>{
+    current_block: &mut BasicBlock<'a>) -> Result<(), String>{
+
+    let new_bb: BasicBlock<'_> = context.insert_basic_block_after(*prev_bb, &_reserved_name(new_bb_name));
 
-    let new_bb: BasicBlock<'_> = context.insert_basic_block_after(*prev_bb, new_bb_name);
+    // In diagnostic mode, failed checks go through a block reporting the violation
+    let fail_bb: BasicBlock<'_> = match diagnostic {
+        Some(_) => context.prepend_basic_block(*abort_bb, &_reserved_name(&format!("{}_violation", new_bb_name))),
+        None => *abort_bb,
+    };
 
     // Create a new builder and position it before the instruction
     let builder: Builder<'_> = context.create_builder();
     builder.position_before(instr);
 
-    let operand_index = match instr.get_opcode() {
-        Load => 0,
-        Store => 1,
-        other => return Err(format!("Expected Load or Store, found {:?}", other))
-    };
+    // Generated instructions have the location of the access
+    _set_debug_location(&builder, instr);
+
+    match accessed_mem {
+
+        Left((accessed_ptr_val, size)) => {
+
+            let alignment_as_int_value: IntValue<'_> = context.i64_type().const_int(size, false);
+
+            let stack_bounds = match stack_alloca {
+                Some(alloca) => Some(_build_alloca_bounds(context, &builder, alloca)?),
+                None => None,
+            };
+
+            _build_check(
+                context, 
+                builder, 
+                protected_mem,
+                (accessed_ptr_val, alignment_as_int_value),
+                stack_bounds,
+                &fail_bb,
+                new_bb,
+                new_bb_name)?;
+
+            if let Some(diagnostic) = diagnostic {
+                let report_builder = context.create_builder();
+                report_builder.position_at_end(fail_bb);
+                _set_debug_location(&report_builder, instr);
+                _build_violation_report(
+                    context,
+                    &report_builder,
+                    diagnostic,
+                    (accessed_ptr_val, alignment_as_int_value),
+                    stack_bounds,
+                    protected_mem,
+                    abort_bb)?;
+            }
 
-    // Extract pointer values
-    let accessed_ptr_val: PointerValue<'_> = match instr.get_operand(operand_index).unwrap().unwrap_left() {
-        BasicValueEnum::PointerValue(ptr) => ptr,
-        _ => return Err("Failed to extract accessed pointer value.".to_string())
-    };
+        }
 
-    // Extract alignment 
-    let alignment_as_int_value: IntValue<'_> = context.i64_type().const_int(
-        instr.get_alignment().expect("Failed to get alignment") as u64, false
-        );
+        Right(accessed_lanes) => {
+
+            let element_size_as_int_value: IntValue<'_> = context.i64_type().const_int(accessed_lanes.2, false);
+
+            let violating_ptr = _build_lanes_check(
+                context, 
+                builder, 
+                protected_mem,
+                accessed_lanes,
+                &fail_bb,
+                new_bb,
+                new_bb_name,
+                diagnostic.is_some())?;
+
+            if let (Some(diagnostic), Some(violating_ptr)) = (diagnostic, violating_ptr) {
+                let report_builder = context.create_builder();
+                report_builder.position_at_end(fail_bb);
+                _set_debug_location(&report_builder, instr);
+                _build_violation_report(
+                    context,
+                    &report_builder,
+                    diagnostic,
+                    (violating_ptr, element_size_as_int_value),
+                    None,
+                    protected_mem,
+                    abort_bb)?;
+            }
 
-    _build_check(
-        context, 
-        builder, 
-        protected_mem,
-        (accessed_ptr_val, alignment_as_int_value),
-        abort_bb,
-        new_bb,
-        new_bb_name)?;
+        }
+
+    }
 
     // Move instructions to the continue_block
     _move_instructions(context, instr, &new_bb);
@@ -429,43 +911,120 @@ fn _handle_store_or_load(
     // Check if there is a branch in the new block.
     // If there is, check if there are phi instructions
     // in the target blocks. If there are, update previous blocks.
-    _check_phi(context, function, &new_bb, current_block_name, phi_counter);
-    *current_block_name = new_bb_name.to_string();
+    _check_phi(context, &new_bb, current_block);
+    *current_block = new_bb;
 
     Ok(())
 }
 
 /// Given a LLVM function adds runtime memory checks
+///
+/// # Returns
+///
+/// Returns the number of accesses of each kind checked, elided or exempted.
 pub fn instrument<'a>(
     function_name: &str, 
     context: &'a Context, 
     module: &Module<'a>,
-    static_analysis: bool) -> Result<(), String> {
+    static_analysis: bool) -> Result<InstrumentationStats, String> {
+
+    let policy = Policy {
+        static_analysis,
+        ..Policy::default()
+    };
+
+    instrument_with_policy(function_name, context, module, &policy)
+
+}
+
+/// Given a LLVM function adds runtime memory checks following the sandbox
+/// rules of `policy`, refusing to instrument it if it calls a function that
+/// is not allowed by the policy.
+///
+/// If `policy.static_analysis` is set, accesses proven inside the protected
+/// region are not checked, and the function is not instrumented if an access
+/// is proven outside of it: only the accesses that cannot be decided
+/// statically are checked at runtime.
+///
+/// If `policy.check_stack_bounds` is set, accesses to stack allocations are
+/// checked against the bounds of the allocation, except the accesses at a
+/// constant offset proven inside it.
+///
+/// If `policy.diagnostics` is set, a failed check prints its site, the
+/// accessed memory and the active region to stderr before aborting.
+///
+/// Instrumented functions are recorded in the module (see
+/// [`is_instrumented`]), and instrumenting a function twice is an error.
+///
+/// # Returns
+///
+/// Returns the number of accesses of each kind checked, elided or exempted.
+pub fn instrument_with_policy<'a>(
+    function_name: &str, 
+    context: &'a Context, 
+    module: &Module<'a>,
+    policy: &Policy) -> Result<InstrumentationStats, String> {
+
+    // Checks are built in the context owning the module
+    if context.as_ctx_ref() != module.get_context().as_ctx_ref() {
+        return Err(format!("The module of {} does not belong to the given context", function_name));
+    }
+
+    instrument_module_function(function_name, module, policy)
+
+}
+
+/// Same as [`instrument_with_policy`], building the checks in the context
+/// owning the module, for modules whose `Context` is not available, e.g.
+/// modules owned by a pass manager.
+pub fn instrument_module_function(
+    function_name: &str, 
+    module: &Module,
+    policy: &Policy) -> Result<InstrumentationStats, String> {
+
+    // Checks are built in the context owning the module
+    let context = &module.get_context();
+
+    // Optional analyses
+    let static_analysis = policy.static_analysis;
 
     // Retrieve function value
     let function = module.get_function(function_name).unwrap();
 
-    // Set a name for every basic block in the code
-    for (i, bb) in function.get_basic_blocks().into_iter().enumerate() {
-        let name = format!("bb{}", i);
-        bb.set_name(&name);
+    // A second layer of checks would be redundant
+    if is_instrumented(module, function_name) {
+        return Err(format!("Function {} is already instrumented", function_name));
     }
 
-    // Define the type of the abort function: fn() -> void
-    let abort_type = context.void_type().fn_type(&[], false);
+    // Check calls before modifying the function
+    policy::check_calls(function, policy)?;
+
+    // Accesses proven outside the protected region are rejected at compile time,
+    // the ones that cannot be proven inside are checked at runtime
+    if static_analysis {
+        if let Some(instr) = static_checks::get_refuted_accesses(module.clone(), function, policy).first() {
+            return Err(format!("Access outside the protected region: {}", instr.print_to_string().to_string().trim()));
+        }
+    }
 
-    // Create the abort function in the module
-    let abort_func = module.add_function("abort", abort_type, None);
+    // Reuse the declaration of the abort function, if any, or declare it: fn() -> void
+    let abort_func = _get_violation_handler(context, module, &policy.violation_handler)?;
+
+    // Function printing violations, in diagnostic mode
+    let print_func = if policy.diagnostics { Some(_get_print_function(context, module)?) } else { None };
 
     // ***** Append abort block ***** //
-    let abort_bb: BasicBlock<'_> = context.append_basic_block(function, "abort");
+    let abort_bb: BasicBlock<'_> = context.append_basic_block(function, &_reserved_name("abort"));
 
     // Create builder and position at the end of the abort basic block
     let abort_builder: Builder<'_> = context.create_builder();
     abort_builder.position_at_end(abort_bb);
 
+    // The abort block is shared by every check, so it has no line of its own
+    _set_function_debug_location(context, &abort_builder, &function);
+
     // Call abort function with noreturn and nounwind attrs
-    let _ = abort_builder.build_call(abort_func, &[], "abort");
+    let _ = abort_builder.build_call(abort_func, &[], "");
 
     /* Add noreturn and nounwind attributes
     call_abort_instr
@@ -478,15 +1037,30 @@ pub fn instrument<'a>(
 
     let _ = abort_builder.build_unreachable();
 
+    // Function and format used to report violations, in diagnostic mode
+    let report_format: Option<(FunctionValue, GlobalValue)> = match print_func {
+        Some(print_func) => {
+            let format = match module.get_global(&_reserved_name("violation_format")) {
+                Some(format) => format,
+                None => abort_builder.build_global_string_ptr(VIOLATION_FORMAT, &_reserved_name("violation_format"))
+                    .map_err(|e| format!("Failed to build violation format string: {:?}", e))?,
+            };
+            Some((print_func, format))
+        }
+        None => None,
+    };
+
     /***** Create a global variable for storing the current protected pointer *****/
     // Pointer type for protected pointer
     let pointer_type = context.i8_type().ptr_type(inkwell::AddressSpace::default());
     // Type of the offset
     let i64_type = context.i64_type();
 
-    // Add globals
-    let protected_ptr: GlobalValue<'_> = module.add_global(pointer_type, None, "protected_ptr");
-    let protected_offset: GlobalValue<'_> = module.add_global(i64_type, None, "protected_offset");
+    // Add globals, shared by the functions of the module
+    let protected_ptr: GlobalValue<'_> = module.get_global(&_reserved_name("protected_ptr"))
+        .unwrap_or_else(|| module.add_global(pointer_type, None, &_reserved_name("protected_ptr")));
+    let protected_offset: GlobalValue<'_> = module.get_global(&_reserved_name("protected_offset"))
+        .unwrap_or_else(|| module.add_global(i64_type, None, &_reserved_name("protected_offset")));
     let protected_mem: (GlobalValue<'_>, GlobalValue<'_>) = (protected_ptr, protected_offset);
 
     // Initialize globals
@@ -496,27 +1070,22 @@ pub fn instrument<'a>(
     let zero_offset = i64_type.const_int(0, false);
     protected_offset.set_initializer(&zero_offset);
 
-    // Stack values
-    let stack_values: Vec<PointerValue> = Vec::new();
+    // Count the number of memory accesses of each kind, to give names to blocks later
+    let mut access_counters: HashMap<&str, u32> = HashMap::new();
 
-    // * Internal state for static analysis * //
-    let mut protected_mem_static: (Option<PointerValue>, Option<u64>) = (None, None);
+    // How the memory accesses of each kind are handled
+    let mut stats = InstrumentationStats::new(function_name);
 
-    // Count the number of load and store instructions, to give names to blocks later
-    let mut load_counter: u32 = 0;
-    let mut store_counter: u32 = 0;
+    let target_data = static_checks::get_target_data(module);
 
-    // Count the number of stack allocations instructions
-    let mut alloca_counter: u32 = 0;
-
-    // Count the number of updated phi instructions, to give names later
-    let mut phi_counter: u32 = 0;
+    // How each memory access is handled, decided before modifying the function
+    let handlings = _get_access_handlings(module, function, policy, &target_data)?;
 
     // Iterate over the basic blocks in the function
     for basic_block in function.get_basic_blocks() {
 
-        let mut current_block_name: String = basic_block.get_name().to_str()
-        .expect("Failed to get basic block name.").to_string();
+        // Block containing the current instruction, after the previous splits
+        let mut current_block: BasicBlock = basic_block;
 
         // Iterate over the instructions in the basic block
         let instructions = basic_block.get_instructions();
@@ -524,133 +1093,103 @@ pub fn instrument<'a>(
 
             match instr.get_opcode() {
 
-                Call => {
-                    // Check if it is the call to `utx1`
-                    if instr.to_string().contains("utx0") {         
-                        // remove utx0 call
-                        instr.erase_from_basic_block();
-                    } else if instr.to_string().contains("utx1") {     // Check if it is the call to `utx1`, Not sure if this is safe
-
-                        // Extract pointer value and offset to protect
-                        let (ptr, offset) = match (instr.get_operand(0), instr.get_operand(1)) {
-                            (Some(Left(BasicValueEnum::PointerValue(ptr))), Some(Left(BasicValueEnum::IntValue(offset)))) => (ptr, offset),
-                            _ => return Err("Failed to extract pointer value and offset".to_string()),
-                        };
-
-                        // Create a new builder and position it before the instruction
-                        let builder = context.create_builder();
-                        builder.position_before(&instr);
-
-                        // Store the pointer value and offset to protect
-                        builder.build_store(protected_ptr.as_pointer_value(), ptr)
-                            .map_err(|e| format!("Failed to store protected pointer value: {:?}", e))?;
-                        builder.build_store(protected_offset.as_pointer_value(), offset)
-                            .map_err(|e| format!("Failed to store protected offset value: {:?}", e))?;
-
-                        if static_analysis {
-                            let (ptr, offset) = static_checks::handle_utx1(instr);
-                            protected_mem_static = (Some(ptr), Some(offset));
-                        }
-
-                        // remove utx1 call
-                        instr.erase_from_basic_block();
-
-                    }
-
+                Call if policy.is_end_marker(instr) => {
+                    // remove utx0 call
+                    instr.erase_from_basic_block();
                 }
 
-                Load => {
-
-                    // If it's stack skip
-                    if instr.print_to_string().to_string().contains("stack") {
-                        continue;
-                    }
-
-                    // Create the block to store the rest of the code
-                    let new_bb_name = format!("load{}", load_counter);
-                    load_counter += 1;
-                    
-                    if static_analysis {
+                Call if policy.is_begin_marker(instr) => {     // Check if it is the call to `utx1`
 
-                        let alignment: u32 = instr.get_alignment()
-                        .expect(&format!("Failed to get the alignment of instruction {:?}", instr));
+                    // Extract pointer value and offset to protect
+                    let (ptr, offset) = match (instr.get_operand(0), instr.get_operand(1)) {
+                        (Some(Left(BasicValueEnum::PointerValue(ptr))), Some(Left(BasicValueEnum::IntValue(offset)))) => (ptr, offset),
+                        _ => return Err("Failed to extract pointer value and offset".to_string()),
+                    };
 
-                        let ptr: PointerValue = match instr.get_operand(0).unwrap().unwrap_left() { 
-                            PV(ptr) => ptr,
-                            other => panic!("Expected PointerValue, found {:?}", other)
-                        };
+                    // Create a new builder and position it before the instruction
+                    let builder = context.create_builder();
+                    builder.position_before(&instr);
+                    _set_debug_location(&builder, &instr);
 
-                        if static_checks::is_address_protected(module.clone(), &protected_mem_static, ptr, alignment as u64) {
-                            continue;
-                        }
+                    // Store the pointer value and offset to protect
+                    builder.build_store(protected_ptr.as_pointer_value(), ptr)
+                        .map_err(|e| format!("Failed to store protected pointer value: {:?}", e))?;
+                    builder.build_store(protected_offset.as_pointer_value(), offset)
+                        .map_err(|e| format!("Failed to store protected offset value: {:?}", e))?;
 
-                    }
-
-                    _handle_store_or_load(
-                        context, 
-                        &function, 
-                        &instr, 
-                        protected_mem, 
-                        &abort_bb, 
-                        &basic_block, 
-                        &new_bb_name, 
-                        &mut current_block_name, 
-                        &mut phi_counter)?;
+                    // remove utx1 call
+                    instr.erase_from_basic_block();
 
                 }
 
-                Store => {
+                _ => {
 
-                    // If it's stack skip
-                    if instr.print_to_string().to_string().contains("stack") {
+                    // Kind of memory access, if the instruction accesses memory
+                    let Some(kind) = static_checks::get_access_kind(instr) else {
                         continue;
+                    };
+
+                    // Extract accessed memory, a range or the lanes of a gather or scatter
+                    let accessed_mem = match static_checks::get_accessed_memory(&target_data, instr) {
+                        Some(range) => Left(range),
+                        None => Right(static_checks::get_accessed_lanes(&target_data, instr)
+                            .ok_or_else(|| format!("Failed to extract accessed memory of {:?}", instr))?),
+                    };
+
+                    // Accesses that are not checked
+                    match handlings.get(&instr) {
+                        Some(AccessHandling::Exempt) => {
+                            stats.kind(kind).exempt += 1;
+                            continue;
+                        }
+                        Some(AccessHandling::Stack) => {
+                            stats.kind(kind).stack += 1;
+                            continue;
+                        }
+                        _ => (),
                     }
 
                     // Create the block to store the rest of the code
-                    let new_bb_name: String = format!("store{}", store_counter);
-                    store_counter += 1;
-
-                    if static_analysis {
-
-                        let alignment: u32 = instr.get_alignment()
-                        .expect(&format!("Failed to get the alignment of instruction {:?}", instr));
-
-                        let ptr: PointerValue = match instr.get_operand(1).unwrap().unwrap_left() { 
-                            PV(ptr) => ptr,
-                            other => panic!("Expected PointerValue, found {:?}", other)
-                        };
-
-                        if static_checks::is_address_protected(module.clone(), &protected_mem_static, ptr, alignment as u64) {
+                    let counter: &mut u32 = access_counters.entry(kind).or_insert(0);
+                    let new_bb_name = format!("{}{}", kind, counter);
+                    *counter += 1;
+
+                    let stack_alloca = match handlings.get(&instr) {
+                        Some(AccessHandling::Checked(stack_alloca)) => *stack_alloca,
+                        // Proven inside the protected region
+                        Some(_) => {
+                            stats.kind(kind).elided += 1;
                             continue;
                         }
+                        None => return Err(format!("Unexpected memory access {}", instr)),
+                    };
 
-                    }
+                    let site = _get_site_description(function_name, kind, instr);
+                    let diagnostic = report_format.map(|(print_function, format)| (print_function, format, site.as_str()));
 
-                    _handle_store_or_load(
+                    _handle_memory_access(
                         context, 
-                        &function, 
                         &instr, 
+                        accessed_mem,
+                        stack_alloca,
                         protected_mem, 
+                        diagnostic,
                         &abort_bb, 
                         &basic_block, 
                         &new_bb_name, 
-                        &mut current_block_name, 
-                        &mut phi_counter)?;
+                        &mut current_block)?;
 
-                }
+                    stats.kind(kind).checked += 1;
 
-                Alloca => {
-                    instr.set_name(&format!("stack_{}", alloca_counter));
-                    alloca_counter += 1;
                 }
 
-                _ => {}
-
             }
 
         }
 
     }
 
-    Ok(())
+    _mark_instrumented(context, module, function_name)?;
+
+    Ok(stats)
 }
@@ -0,0 +1,193 @@
+//! SMT backend for the accesses the symbolic model cannot decide.
+//!
+//! With the `smt` feature, the distance between an access and the protected
+//! region (see `symbolic::get_distance`) and the branch conditions known where
+//! the access happens (see `ranges::get_block_facts`) are encoded as bitvector
+//! formulas and handed to Z3.  Unlike the ranges of the symbolic model, the
+//! solver can use relations between variables, e.g. `i < j` and `j < 100`.
+//!
+//! Integer values are encoded with their own width and wrapping arithmetic,
+//! offsets are computed on 128 bits so that they cannot overflow.
+
+use std::collections::HashMap;
+
+use inkwell::targets::TargetData;
+use inkwell::values::{IntValue, PointerValue};
+use inkwell::values::BasicValueEnum::IntValue as IV;
+use inkwell::values::InstructionOpcode::{SExt, ZExt};
+use inkwell::IntPredicate;
+use z3::ast::{Ast, Bool, BV};
+use z3::{Config, Context, Params, SatResult, Solver};
+
+use crate::ranges::{Condition, Facts};
+use crate::symbolic::{self, AffineExpr, Verdict};
+
+/// Width of the bitvectors encoding offsets.
+const OFFSET_WIDTH: u32 = 128;
+
+/// Time limit of each query to the solver, in milliseconds.
+const TIMEOUT_MS: u32 = 1000;
+
+/// Encodes LLVM integer values as bitvectors of a solver context.
+struct _Encoder<'ctx, 'a> {
+    context: &'ctx Context,
+    variables: HashMap<IntValue<'a>, BV<'ctx>>,
+}
+
+impl<'ctx, 'a> _Encoder<'ctx, 'a> {
+
+    /// Returns the bitvector of an integer value, with the width of the value.
+    fn get_variable(&mut self, value: IntValue<'a>) -> BV<'ctx> {
+
+        let width = value.get_type().get_bit_width();
+
+        if let Some(constant) = value.get_sign_extended_constant() {
+            return BV::from_i64(self.context, constant, width);
+        }
+
+        if let Some(variable) = self.variables.get(&value) {
+            return variable.clone();
+        }
+
+        // Extensions are linked to the extended value, e.g. a 32 bits
+        // index compared in a branch and extended to 64 bits for a `getelementptr`
+        let extended = value.as_instruction().and_then(|instr| {
+            let Some(IV(operand)) = instr.get_operand(0).and_then(|operand| operand.left()) else {
+                return None;
+            };
+            let extension = width - operand.get_type().get_bit_width();
+            match instr.get_opcode() {
+                SExt => Some(self.get_variable(operand).sign_ext(extension)),
+                ZExt => Some(self.get_variable(operand).zero_ext(extension)),
+                _ => None,
+            }
+        });
+
+        let variable = extended.unwrap_or_else(|| BV::new_const(self.context, format!("v{}", self.variables.len()), width));
+
+        self.variables.insert(value, variable.clone());
+
+        variable
+
+    }
+
+    /// Encodes an affine expression on `width` bits, sign extending narrower variables.
+    fn encode_affine(&mut self, expr: &AffineExpr<'a>, width: u32) -> BV<'ctx> {
+
+        let mut encoded = BV::from_i64(self.context, expr.constant, width);
+
+        for (variable, coefficient) in &expr.terms {
+
+            let mut variable = self.get_variable(*variable);
+            if variable.get_size() < width {
+                variable = variable.sign_ext(width - variable.get_size());
+            }
+
+            encoded = encoded.bvadd(&variable.bvmul(&BV::from_i64(self.context, *coefficient, width)));
+
+        }
+
+        encoded
+
+    }
+
+    /// Encodes a comparison known to hold.
+    fn encode_condition(&mut self, target_data: &TargetData, condition: &Condition<'a>) -> Bool<'ctx> {
+
+        let (predicate, lhs, rhs) = *condition;
+        let width = lhs.get_type().get_bit_width();
+
+        let lhs = self.encode_affine(&symbolic::get_affine_expr(target_data, lhs), width);
+        let rhs = self.encode_affine(&symbolic::get_affine_expr(target_data, rhs), width);
+
+        match predicate {
+            IntPredicate::EQ => lhs._eq(&rhs),
+            IntPredicate::NE => lhs._eq(&rhs).not(),
+            IntPredicate::UGT => lhs.bvugt(&rhs),
+            IntPredicate::UGE => lhs.bvuge(&rhs),
+            IntPredicate::ULT => lhs.bvult(&rhs),
+            IntPredicate::ULE => lhs.bvule(&rhs),
+            IntPredicate::SGT => lhs.bvsgt(&rhs),
+            IntPredicate::SGE => lhs.bvsge(&rhs),
+            IntPredicate::SLT => lhs.bvslt(&rhs),
+            IntPredicate::SLE => lhs.bvsle(&rhs),
+        }
+
+    }
+
+}
+
+/// Checks if an access is inside a protected region with an SMT solver.
+///
+/// # Arguments
+///
+/// * `target_data` - The target data of the module, used to compute offsets.
+/// * `protected_mem` - The protected region, as its pointer and length.
+/// * `ptr` - The accessed pointer.
+/// * `size` - The number of bytes accessed.
+/// * `facts` - The facts known where the access happens.
+///
+/// # Returns
+///
+/// Returns [`Verdict::Proven`] if the access is inside the region whenever
+/// the facts hold, [`Verdict::Refuted`] if it is always outside, and
+/// [`Verdict::Unknown`] otherwise or if the solver times out.
+pub fn check_access(target_data: &TargetData, protected_mem: (PointerValue, IntValue), ptr: PointerValue, size: u64, facts: &Facts) -> Verdict {
+
+    let (protected_ptr, protected_len) = protected_mem;
+
+    // Distance in bytes between the start of the region and the access
+    let distance = match symbolic::get_distance(target_data, protected_ptr, ptr) {
+        Ok(distance) => distance,
+        Err(verdict) => return verdict,
+    };
+
+    // A wrapped length is not the size of the region
+    let Some(protected_len) = symbolic::get_length_expr(target_data, protected_len, &facts.ranges) else {
+        return Verdict::Unknown;
+    };
+
+    let config = Config::new();
+    let context = Context::new(&config);
+    let solver = Solver::new(&context);
+
+    let mut params = Params::new(&context);
+    params.set_u32("timeout", TIMEOUT_MS);
+    solver.set_params(&params);
+
+    let mut encoder = _Encoder { context: &context, variables: HashMap::new() };
+
+    for condition in &facts.conditions {
+        solver.assert(&encoder.encode_condition(target_data, condition));
+    }
+
+    // distance >= 0 && distance + size <= protected_len
+    let distance = encoder.encode_affine(&distance, OFFSET_WIDTH);
+    let zero = BV::from_u64(&context, 0, OFFSET_WIDTH);
+    let size = BV::from_u64(&context, size, OFFSET_WIDTH);
+    let protected_len = encoder.encode_affine(&protected_len, OFFSET_WIDTH);
+    let protected = Bool::and(&context, &[&distance.bvsge(&zero), &distance.bvadd(&size).bvsle(&protected_len)]);
+
+    // Proven if the access cannot be outside the region
+    solver.push();
+    solver.assert(&protected.not());
+    let outside = solver.check();
+    solver.pop(1);
+
+    if outside == SatResult::Unsat {
+        return Verdict::Proven;
+    }
+
+    // Refuted if the access cannot be inside the region
+    solver.push();
+    solver.assert(&protected);
+    let inside = solver.check();
+    solver.pop(1);
+
+    if inside == SatResult::Unsat {
+        return Verdict::Refuted;
+    }
+
+    Verdict::Unknown
+
+}
@@ -35,11 +35,29 @@
 //! ```
 
 use inkwell::module::Module;
-use inkwell::values::{AnyValue, FunctionValue, InstructionValue};
-use inkwell::values::PointerValue;
-use inkwell::values::BasicValueEnum::{PointerValue as PV, IntValue as IV};
-use inkwell::values::InstructionOpcode::{Call, Load, Store};
-use regex::Regex;
+use inkwell::values::{AsValueRef, FunctionValue, GlobalValue, InstructionValue, VectorValue};
+use inkwell::values::{IntValue, PointerValue};
+use inkwell::values::BasicValueEnum::{PointerValue as PV, IntValue as IV, VectorValue as VV};
+use inkwell::values::InstructionOpcode::{Call, Load, Store, AtomicRMW, AtomicCmpXchg, Alloca, GetElementPtr};
+use inkwell::targets::TargetData;
+use inkwell::types::{AnyType, AnyTypeEnum, BasicTypeEnum};
+
+extern crate llvm_sys as llvm;
+
+use crate::policy::{self, Policy};
+use crate::report::{Violation, ViolationKind};
+use crate::symbolic::{self, Verdict};
+use crate::ranges::{self, Facts};
+#[cfg(feature = "smt")]
+use crate::smt;
+
+/// Masked vector intrinsics accessing memory, with their access kind.
+const MASKED_INTRINSICS: [(&str, &str); 4] = [
+    ("llvm.masked.load.", "masked_load"),
+    ("llvm.masked.store.", "masked_store"),
+    ("llvm.masked.gather.", "gather"),
+    ("llvm.masked.scatter.", "scatter"),
+];
 
 /// Removes a specific function call from the body of an LLVM IR function.
 ///
@@ -76,144 +94,400 @@ pub fn remove_function_call(module: &Module, caller_name: &str, callee_name: &st
 
 }
 
-fn _get_type_as_str_size(type_as_str: &str) -> i64 {
+/// Checks if a given pointer value is in a protected range.
+///
+/// This function compares the provided pointer value against a protected memory address and offset.
+/// If the pointer value matches the protected address and the offset is within the protected range,
+/// the access is proven safe, unless either pointer is cast from an integer (see [`is_int_to_ptr`]).
+/// Otherwise pointers are compared symbolically with the protected
+/// address (see [`symbolic::check_access`]), e.g. `&shared_array[index]` against
+/// `&shared_array[index + 1]` using the known ranges of the indices, `&ring.buf[3]` against
+/// `&ring` using the struct layout, or `&matrix[2][3]` against `&matrix[2]`, whatever the depth
+/// of the (possibly nested) `getelementptr` constant expressions.
+///
+/// # Arguments
+///
+/// * `module` - The LLVM module, giving the layout of the indexed types.
+/// * `protected_mem` - A tuple containing the protected memory address and offset, which
+///   can be any value, e.g. the result of `malloc` and its size or a pointer argument.
+/// * `ptr` - The pointer value to check for protection.
+/// * `alignment` - The alignment associated with the pointer value.
+/// * `facts` - The facts known where the pointer is accessed
+///   (see [`ranges::get_block_facts`]).
+///
+/// # Returns
+///
+/// Returns [`Verdict::Proven`] if the access is inside the protected range, [`Verdict::Refuted`]
+/// if it is outside, and [`Verdict::Unknown`] if it cannot be decided statically (e.g. without
+/// a protected range).
+pub fn check_address(
+    module: Module,
+    protected_mem: &(Option<PointerValue>, Option<IntValue>), 
+    ptr: PointerValue, 
+    alignment: u64,
+    facts: &Facts) -> Verdict {
+
+    let (Some(protected_ptr), Some(protected_offset)) = *protected_mem else { return Verdict::Unknown; };
 
-    match type_as_str {
-        "i64" => 8,
-        other => panic!("Expected type, found {}", other)
+    // Pointers cast from integers can point anywhere, even if they are equal to the protected pointer
+    if is_int_to_ptr(ptr) || is_int_to_ptr(protected_ptr) {
+        return Verdict::Unknown;
     }
 
-}
+    // Protected pointer and pointer accessed are the same
+    if protected_ptr == ptr && matches!(protected_offset.get_zero_extended_constant(), Some(offset) if offset >= alignment) {
+
+        return Verdict::Proven;
+
+    } 
+
+    let target_data = get_target_data(&module);
+
+    // Compare the pointer symbolically with the protected pointer, following
+    // the layout of the indexed types (e.g. struct fields and nested arrays)
+    let verdict = symbolic::check_access(&target_data, (protected_ptr, protected_offset), ptr, alignment, &facts.ranges);
 
-fn _parse_gep(gep: PointerValue) -> Vec<(String, String)> {
+    if verdict != Verdict::Unknown { return verdict }
 
-    let gep_as_llvmstring = gep.print_to_string();
-    let gep_as_str: &str = gep_as_llvmstring.to_str().expect("Failed to convert LLVMString to &str.");
+    // Hand the accesses the symbolic model cannot decide to the solver
+    #[cfg(feature = "smt")]
+    if !ptr.is_const() {
+        return smt::check_access(&target_data, (protected_ptr, protected_offset), ptr, alignment, facts);
+    }
 
-    // (type, value)
-    let mut operands: Vec<(String, String)> = Vec::new();
+    Verdict::Unknown
 
-    // Get type
-    let gep_type_pattern: Regex = Regex::new(r"(\w)+ x (\w)+").unwrap();
+}
 
-    let capture = gep_type_pattern.captures(gep_as_str).unwrap();
+/// Checks if a given pointer value is in a protected range.
+///
+/// Same as [`check_address`], for accesses that must be proven safe.
+///
+/// # Returns
+///
+/// Returns true if the pointer value is protected, false otherwise.
+pub fn is_address_protected(
+    module: Module,
+    protected_mem: &(Option<PointerValue>, Option<IntValue>), 
+    ptr: PointerValue, 
+    alignment: u64,
+    facts: &Facts) -> bool {
 
-    let binding = capture[0].to_string().replace(" x ", " ");
-    let array_type_and_size_as_string: Vec<_> = binding.split_whitespace().collect();
+    check_address(module, protected_mem, ptr, alignment, facts) == Verdict::Proven
 
-    let array_size= array_type_and_size_as_string[0].to_string();
-    let array_type= array_type_and_size_as_string[1].to_string();
+}
 
-    operands.push((array_size, array_type));
+/// Returns the target data of a module, used to compute type sizes, e.g. the
+/// number of bytes accessed by atomic and vector instructions.
+pub fn get_target_data(module: &Module) -> TargetData {
 
-    // Pattern to match GEP operands
-    let gep_operands_pattern: Regex = Regex::new(r", (\w)+ @?(\w)+").unwrap();
+    let data_layout = module.get_data_layout();
 
-    for capture in gep_operands_pattern.captures_iter(gep_as_str) {
+    TargetData::create(data_layout.as_str().to_str().expect("Failed to convert data layout to &str."))
 
-        let binding: String = capture[0].to_string().replace(",", "");
-        let parts: Vec<_> = binding.split_whitespace().collect();
+}
 
-        operands.push((parts[0].to_string(), parts[1].to_string()));
+/// Returns the kind of memory access performed by an instruction.
+///
+/// The kind is one of `load`, `store`, `atomicrmw`, `cmpxchg`, `masked_load`,
+/// `masked_store`, `gather` and `scatter`.
+///
+/// # Returns
+///
+/// Returns `None` if the instruction does not access memory.
+pub fn get_access_kind(instr: InstructionValue) -> Option<&'static str> {
+
+    match instr.get_opcode() {
+        Load => Some("load"),
+        Store => Some("store"),
+        AtomicRMW => Some("atomicrmw"),
+        AtomicCmpXchg => Some("cmpxchg"),
+        Call => {
+            let callee = policy::get_called_function_name(instr)?;
+            MASKED_INTRINSICS.iter()
+                .find(|(prefix, _)| callee.starts_with(prefix))
+                .map(|(_, kind)| *kind)
+        }
+        _ => None,
     }
 
-    operands
+}
+
+/// Extracts the pointer accessed by a memory instruction and the access size.
+///
+/// The size of loads and stores is the store size of the loaded or stored
+/// value, e.g. 32 bytes for a `<4 x i64>`, the size of `atomicrmw` and
+/// `cmpxchg` is the store size of the value operand, and the size of masked
+/// loads and stores is the store size of the whole vector, regardless of the
+/// mask.
+///
+/// # Returns
+///
+/// Returns `None` if the instruction does not access a range of memory
+/// (e.g. gathers and scatters, see [`get_accessed_lanes`]).
+pub fn get_accessed_memory<'a>(target_data: &TargetData, instr: InstructionValue<'a>) -> Option<(PointerValue<'a>, u64)> {
+
+    let kind = get_access_kind(instr)?;
+
+    let ptr_index = match kind {
+        "load" | "atomicrmw" | "cmpxchg" | "masked_load" => 0,
+        "store" | "masked_store" => 1,
+        _ => return None,
+    };
+
+    let ptr: PointerValue = match instr.get_operand(ptr_index)?.left()? {
+        PV(ptr) => ptr,
+        _ => return None,
+    };
+
+    let size: u64 = match kind {
+        // The loaded value or vector is the result of the instruction
+        "load" | "masked_load" => target_data.get_store_size(&instr.get_type()),
+        // The stored value or vector is the first operand
+        "store" | "masked_store" => target_data.get_store_size(&instr.get_operand(0)?.left()?.get_type()),
+        // The value operand is the new value for atomicrmw and the compared value for cmpxchg
+        _ => target_data.get_store_size(&instr.get_operand(1)?.left()?.get_type()),
+    };
+
+    Some((ptr, size))
 
 }
 
-/// Checks if a given pointer value is in a protected range.
+/// Extracts the lanes accessed by a gather or scatter intrinsic.
 ///
-/// This function compares the provided pointer value against a protected memory address and offset.
-/// If the pointer value matches the protected address and the offset is within the protected range,
-/// the function returns true, indicating that the memory access is protected.
+/// # Returns
 ///
-/// # Arguments
+/// Returns the vector of accessed pointers, the mask of enabled lanes and the
+/// size of the element accessed by each lane, or `None` if the instruction is
+/// not a gather or a scatter.
+pub fn get_accessed_lanes<'a>(target_data: &TargetData, instr: InstructionValue<'a>) -> Option<(VectorValue<'a>, VectorValue<'a>, u64)> {
+
+    // gather(ptrs, alignment, mask, passthru) and scatter(value, ptrs, alignment, mask)
+    let (ptrs_index, value_type) = match get_access_kind(instr)? {
+        "gather" => (0, instr.get_type()),
+        "scatter" => (1, instr.get_operand(0)?.left()?.get_type().as_any_type_enum()),
+        _ => return None,
+    };
+
+    let ptrs: VectorValue = match instr.get_operand(ptrs_index)?.left()? {
+        VV(ptrs) => ptrs,
+        _ => return None,
+    };
+
+    let mask: VectorValue = match instr.get_operand(ptrs_index + 2)?.left()? {
+        VV(mask) => mask,
+        _ => return None,
+    };
+
+    let element_size: u64 = match value_type {
+        AnyTypeEnum::VectorType(vector_type) => target_data.get_store_size(&vector_type.get_element_type()),
+        _ => return None,
+    };
+
+    Some((ptrs, mask, element_size))
+
+}
+
+/// Finds the global variable a pointer is derived from through `getelementptr`
+/// instructions and constant expressions.
 ///
-/// * `module` - The LLVM module containing the global variables.
-/// * `protected_mem` - A tuple containing the protected memory address and offset.
-/// * `ptr` - The pointer value to check for protection.
-/// * `alignment` - The alignment associated with the pointer value.
+/// # Returns
+///
+/// Returns `None` if the pointer is not derived from a global variable.
+pub fn get_global_base(ptr: PointerValue) -> Option<GlobalValue> {
+
+    let mut value = ptr.as_value_ref();
+
+    unsafe {
+
+        while llvm::core::LLVMIsAGlobalVariable(value).is_null() {
+
+            let is_gep_instr = !llvm::core::LLVMIsAGetElementPtrInst(value).is_null();
+            let is_gep_expr = !llvm::core::LLVMIsAConstantExpr(value).is_null()
+                && llvm::core::LLVMGetConstOpcode(value) == llvm::LLVMOpcode::LLVMGetElementPtr;
+
+            if !is_gep_instr && !is_gep_expr {
+                return None;
+            }
+
+            // The base pointer is the first operand
+            value = llvm::core::LLVMGetOperand(value, 0);
+
+        }
+
+        Some(GlobalValue::new(value))
+
+    }
+
+}
+
+/// Checks if a pointer is cast from an integer (`inttoptr`), directly or
+/// through `getelementptr`s, `select`s and `phi`s.
+///
+/// Such a pointer has lost its provenance: whatever the integer was computed
+/// from, e.g. `(uint64_t *) ((uintptr_t) &shared_array + offset)`, it can
+/// point anywhere, so accesses through it are never proven safe statically.
+pub fn is_int_to_ptr(ptr: PointerValue) -> bool {
+
+    let mut worklist = vec![ptr.as_value_ref()];
+    let mut visited = Vec::new();
+
+    while let Some(value) = worklist.pop() {
+
+        if visited.contains(&value) {
+            continue;
+        }
+        visited.push(value);
+
+        unsafe {
+
+            let const_opcode = if llvm::core::LLVMIsAConstantExpr(value).is_null() {
+                None
+            } else {
+                Some(llvm::core::LLVMGetConstOpcode(value))
+            };
+
+            // Casts from integers
+            if !llvm::core::LLVMIsAIntToPtrInst(value).is_null() || const_opcode == Some(llvm::LLVMOpcode::LLVMIntToPtr) {
+                return true;
+            }
+
+            // Follow the base pointer of `getelementptr`s,
+            // both values of `select`s and the incoming values of `phi`s
+            if !llvm::core::LLVMIsAGetElementPtrInst(value).is_null() || const_opcode == Some(llvm::LLVMOpcode::LLVMGetElementPtr) {
+                worklist.push(llvm::core::LLVMGetOperand(value, 0));
+            } else if !llvm::core::LLVMIsASelectInst(value).is_null() {
+                worklist.push(llvm::core::LLVMGetOperand(value, 1));
+                worklist.push(llvm::core::LLVMGetOperand(value, 2));
+            } else if !llvm::core::LLVMIsAPHINode(value).is_null() {
+                for i in 0..llvm::core::LLVMCountIncoming(value) {
+                    worklist.push(llvm::core::LLVMGetIncomingValue(value, i));
+                }
+            }
+
+        }
+
+    }
+
+    false
+
+}
+
+/// Finds the stack allocation a pointer is derived from through `getelementptr`s.
 ///
 /// # Returns
 ///
-/// Returns true if the pointer value is protected, false otherwise.
-pub fn is_address_protected(
-    module: Module,
-    protected_mem: &(Option<PointerValue>, Option<u64>), 
-    ptr: PointerValue, 
-    alignment: u64) -> bool {
+/// Returns the `alloca` instruction and whether the pointer is at a constant
+/// offset from it, or `None` if the pointer is not derived from an `alloca`.
+pub fn get_stack_base(ptr: PointerValue) -> Option<(InstructionValue, bool)> {
 
-    let (Some(protected_ptr), Some(protected_offset)) = *protected_mem else { return false; };
+    let instr = ptr.as_instruction()?;
 
-    // Protected pointer and pointer accessed are the same
-    if protected_ptr == ptr && protected_offset >= alignment {
+    match instr.get_opcode() {
 
-        return true;
+        Alloca => Some((instr, true)),
 
-    } 
+        GetElementPtr => {
 
-    // If the pointer is not constant, the value cannot be computed statically
-    if !ptr.is_const() { return false }
+            let base: PointerValue = match instr.get_operand(0)?.left()? {
+                PV(base) => base,
+                _ => return None,
+            };
 
-    // Parse get element pointer and get base pointer, offset and type size
-    let gep_operands: Vec<(String, String)> = _parse_gep(ptr);
+            let (alloca, constant_offset) = get_stack_base(base)?;
 
-    let size: i64 = _get_type_as_str_size(&gep_operands[0].1);
+            // The offset is constant if every index is constant
+            let constant_indices = (1..instr.get_num_operands()).all(|i| {
+                matches!(instr.get_operand(i).and_then(|operand| operand.left()), Some(IV(index)) if index.is_const())
+            });
+
+            Some((alloca, constant_offset && constant_indices))
+
+        }
 
-    // The first operand is the base pointer
-    // Remove the '@'
-    let base_ptr_as_string: String = gep_operands[1].1.replace("@", "");
+        _ => None,
 
-    let first_index: i64 = gep_operands[2].1.parse().unwrap();
+    }
 
-    // Overflow
-    if first_index > 0 { return  false }
+}
 
-    let offset: i64 = gep_operands[3].1.parse().unwrap();
+/// Checks if an access is proven inside the stack allocation `alloca` it is
+/// derived from (see [`get_stack_base`]).
+///
+/// Allocations of a dynamic number of elements, i.e. variable length arrays,
+/// are never proven.
+pub fn is_stack_access_in_bounds(target_data: &TargetData, alloca: InstructionValue, ptr: PointerValue, size: u64, facts: &Facts) -> bool {
 
-    // Get base pointer from base pointer string
-    let base_ptr: PointerValue = match module.get_global(&base_ptr_as_string.as_str()) {
-        Some(global_val) => global_val.as_pointer_value(),
-        None => todo!()
+    let (Ok(alloca_ptr), Ok(allocated_type)) = (PointerValue::try_from(alloca), alloca.get_allocated_type()) else {
+        return false;
     };
 
-    // Check if the base pointer is protected and if the accessed pointer is inside the protected range
-    if protected_ptr.print_to_string() == base_ptr.print_to_string() && (protected_offset as i64) >= offset * size {
+    let Some(count) = alloca.get_operand(0).and_then(|operand| operand.left()).and_then(|count| match count {
+        IV(count) => count.get_zero_extended_constant(),
+        _ => None,
+    }) else {
+        return false;
+    };
 
-        return true;
+    // The allocation is a region of `count` elements of the allocated type
+    let alloca_size = ptr.get_type().get_context().i64_type().const_int(count * target_data.get_abi_size(&allocated_type), false);
 
-    }
+    symbolic::check_access(target_data, (alloca_ptr, alloca_size), ptr, size, &facts.ranges) == Verdict::Proven
 
-    false // No match found, return false
 }
 
-pub fn handle_utx1(instr: InstructionValue) -> (PointerValue, u64) {
+/// Returns the pointer and the length of the region protected by a call to `utx1`.
+///
+/// The length is an SSA value, constant or not, e.g. `n * sizeof (uint64_t)`
+/// for a buffer of `n` elements.
+///
+/// # Returns
+///
+/// Returns an error if the instruction is not a call taking a pointer and an
+/// integer, e.g. a begin marker of another type configured in the policy.
+pub fn handle_utx1(instr: InstructionValue) -> Result<(PointerValue, IntValue), String> {
 
-    if instr.get_opcode() != Call {
-        panic!("Instruction is not a Call.");
+    // A call has its arguments and the called function as operands
+    if instr.get_opcode() != Call || instr.get_num_operands() != 3 {
+        return Err(format!("Expected a call with a pointer and a length, found {}", instr));
     }
 
     // Extract pointer value and offset to protect
-    let ptr: PointerValue = match instr.get_operand(0).unwrap().unwrap_left() {
-        PV(ptr) => ptr,
-        other => panic!("Expected PointerValue, found {:?}", other),
-    };
+    match (instr.get_operand(0).and_then(|operand| operand.left()), instr.get_operand(1).and_then(|operand| operand.left())) {
+        (Some(PV(ptr)), Some(IV(offset))) => Ok((ptr, offset)),
+        _ => Err(format!("Expected a call with a pointer and a length, found {}", instr)),
+    }
 
-    let offset = match instr.get_operand(1).unwrap().unwrap_left() {
-        IV(offset) => offset,
-        other => panic!("Expected IntValue, found {:?}", other),
+}
+
+/// Checks if an access is exempt from checks by the policy (see
+/// [`Policy::is_exempt_access`]).
+///
+/// The access must be proven inside the global it is derived from, since a
+/// dynamic index off an exempt global, e.g. `config[index]`, could otherwise
+/// reach any address.
+pub fn is_exempt_access(target_data: &TargetData, policy: &Policy, ptr: PointerValue, size: u64, kind: &str, facts: &Facts) -> bool {
+
+    let Some(global) = get_global_base(ptr) else {
+        return false;
     };
 
-    let offset_as_u64 = offset.get_zero_extended_constant().expect("Failed to get offset.");
+    if !policy.is_exempt_access(global, kind) {
+        return false;
+    }
+
+    // The global is a region of the size of its value type
+    let global_type = unsafe { BasicTypeEnum::new(llvm::core::LLVMGlobalGetValueType(global.as_value_ref())) };
+    let global_size = ptr.get_type().get_context().i64_type().const_int(target_data.get_abi_size(&global_type), false);
 
-    (ptr, offset_as_u64)
+    symbolic::check_access(target_data, (global.as_pointer_value(), global_size), ptr, size, &facts.ranges) == Verdict::Proven
 
 }
 
 /// Statically verifies that memory accesses within a function are safe.
 ///
 /// This function iterates over the instructions in a function's basic blocks,
-/// checking for load and store operations. It ensures that memory accesses
+/// checking for load, store, atomic and masked vector operations. It ensures that memory accesses
 /// do not violate protected memory addresses.
 ///
 /// # Arguments
@@ -226,72 +500,170 @@ pub fn handle_utx1(instr: InstructionValue) -> (PointerValue, u64) {
 /// Returns `true` if memory access checks pass, `false` otherwise.
 pub fn verify(module: Module, function: FunctionValue) -> bool {
 
+    verify_with_policy(module, function, &Policy::default())
+
+}
+
+/// Statically verifies memory accesses and calls within a function.
+///
+/// Same as [`verify`], but the transaction markers and the exempt globals are
+/// taken from `policy`, and the function is also rejected if it calls a
+/// function that is not allowed by the policy.
+///
+/// # Arguments
+///
+/// * `module` - The LLVM module containing the function.
+/// * `function` - The LLVM IR function to verify.
+/// * `policy` - The sandbox rules of the transaction.
+///
+/// # Returns
+///
+/// Returns `true` if memory access and call checks pass, `false` otherwise.
+pub fn verify_with_policy(module: Module, function: FunctionValue, policy: &Policy) -> bool {
+
+    get_violations(module, function, policy).is_empty()
+
+}
+
+/// Finds every violation of the sandbox rules within a function.
+///
+/// Calls and memory accesses are checked like in [`verify_with_policy`], but
+/// the check does not stop at the first violation.
+///
+/// # Arguments
+///
+/// * `module` - The LLVM module containing the function.
+/// * `function` - The LLVM IR function to verify.
+/// * `policy` - The sandbox rules of the transaction.
+///
+/// # Returns
+///
+/// Returns the violations, disallowed calls first and then memory accesses
+/// in the order of the function.
+pub fn get_violations(module: Module, function: FunctionValue, policy: &Policy) -> Vec<Violation> {
+
+    let function_name = function.get_name().to_str().unwrap_or_default();
+
+    let disallowed_calls = policy::get_disallowed_calls(function, policy)
+        .into_iter()
+        .map(|(instr, message)| (instr, ViolationKind::DisallowedCall, message));
+
+    disallowed_calls
+        .chain(_get_access_violations(module, function, policy))
+        .map(|(instr, kind, message)| Violation::new(kind, function_name, instr, &message))
+        .collect()
+
+}
+
+/// Finds the memory accesses of a function that are proven outside the protected region.
+///
+/// Accesses are checked like in [`verify_with_policy`], but only the ones refuted statically
+/// (see [`check_address`]) are returned: an access that cannot be decided is not refuted.
+///
+/// # Arguments
+///
+/// * `module` - The LLVM module containing the function.
+/// * `function` - The LLVM IR function to check.
+/// * `policy` - The sandbox rules of the transaction.
+///
+/// # Returns
+///
+/// Returns the refuted accesses, in the order of the function.
+pub fn get_refuted_accesses<'a>(module: Module<'a>, function: FunctionValue<'a>, policy: &Policy) -> Vec<InstructionValue<'a>> {
+
+    _get_access_violations(module, function, policy)
+        .into_iter()
+        .filter(|(_, kind, _)| *kind == ViolationKind::OutOfRegionAccess)
+        .map(|(instr, _, _)| instr)
+        .collect()
+
+}
+
+/// Checks the begin markers and the memory accesses of a function against
+/// the protected region, in the order of the function.
+///
+/// # Returns
+///
+/// Returns the instructions violating the sandbox rules, with the kind of
+/// violation and its message.
+fn _get_access_violations<'a>(module: Module<'a>, function: FunctionValue<'a>, policy: &Policy) -> Vec<(InstructionValue<'a>, ViolationKind, String)> {
+
+    let mut violations: Vec<(InstructionValue, ViolationKind, String)> = Vec::new();
+
     // Keeps track of protected memory addresses
     // (pointer, offset)
-    let mut protected_mem: (Option<PointerValue>, Option<u64>) = (None, None);
+    let mut protected_mem: (Option<PointerValue>, Option<IntValue>) = (None, None);
+
+    let target_data = get_target_data(&module);
+
+    // Facts on the indices known from the branch conditions
+    let block_facts = ranges::get_block_facts(function);
+    let no_facts = Facts::default();
 
     // Iterate over the basic blocks in the function
     for bb in function.get_basic_blocks() {
 
+        let facts = block_facts.get(&bb).unwrap_or(&no_facts);
+
         // Iterate over the instructions in the basic block
         for instr in bb.get_instructions() {
 
             match instr.get_opcode() {
 
-                Call => {
+                // Check if it is the call to `utx1`
+                Call if policy.is_begin_marker(instr) => {
 
-                    // Check if it is the call to `utx1`
-                    if instr.to_string().contains("utx1") {         // Not sure if this is safe
-
-                        let (ptr, offset) = handle_utx1(instr);
-
-                        protected_mem = (Some(ptr), Some(offset));
+                    protected_mem = match handle_utx1(instr) {
+                        Ok((ptr, offset)) => (Some(ptr), Some(offset)),
+                        Err(err) => {
+                            violations.push((instr, ViolationKind::InvalidMarker, err));
+                            (None, None)
+                        }
+                    };
 
-                    }
-                
                 }
 
-                Load => {
+                _ => {
 
-                    let alignment: u32 = instr.get_alignment()
-                    .expect(&format!("Failed to get the alignment of instruction {:?}", instr));
+                    // Skip instructions that do not access memory
+                    let Some(kind) = get_access_kind(instr) else {
+                        continue;
+                    };
 
-                    let ptr: PointerValue = match instr.get_operand(0).unwrap().unwrap_left() { 
-                        PV(ptr) => ptr,
-                        other => panic!("Expected PointerValue, found {:?}", other)
+                    // The pointers accessed by gathers and scatters cannot be computed statically
+                    let Some((ptr, size)) = get_accessed_memory(&target_data, instr) else {
+                        violations.push((instr, ViolationKind::UnanalysableAccess,
+                            "Accessed pointers cannot be computed statically".to_string()));
+                        continue;
                     };
 
-                    if !is_address_protected(module.clone(), &protected_mem, ptr, alignment as u64) {
-                        return false;
+                    if is_exempt_access(&target_data, policy, ptr, size, kind, facts) {
+                        continue;
                     }
 
-                }
-
-                Store => {
-
-                    let alignment: u32 = instr.get_alignment()
-                    .expect(&format!("Failed to get the alignment of instruction {:?}", instr));
-
-                    let ptr: PointerValue = match instr.get_operand(1).unwrap().unwrap_left() { 
-                        PV(ptr) => ptr,
-                        other => panic!("Expected PointerValue, found {:?}", other)
-                    };
+                    // The provenance of pointers cast from integers cannot be verified
+                    if is_int_to_ptr(ptr) {
+                        violations.push((instr, ViolationKind::IntToPtrAccess,
+                            "Access through a pointer cast from an integer, whose provenance cannot be verified".to_string()));
+                        continue;
+                    }
 
-                    if !is_address_protected(module.clone(), &protected_mem, ptr, alignment as u64) {
-                        return false;
+                    match check_address(module.clone(), &protected_mem, ptr, size, facts) {
+                        Verdict::Proven => (),
+                        Verdict::Refuted => violations.push((instr, ViolationKind::OutOfRegionAccess,
+                            "Access outside the protected region".to_string())),
+                        Verdict::Unknown => violations.push((instr, ViolationKind::UnprotectedAccess,
+                            "Access not proven inside the protected region".to_string())),
                     }
 
                 }
 
-                _ => ()
-
             }
 
         }
 
     }
 
-    // Check passes
-    true
+    violations
 
 }
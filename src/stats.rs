@@ -0,0 +1,99 @@
+//! Statistics on the memory accesses handled by the instrumentation.
+//!
+//! `runtime::instrument` returns an [`InstrumentationStats`] telling, for
+//! each kind of access, how many accesses were checked at runtime, elided
+//! because they were proven safe statically, or not checked because they
+//! access the stack or an exempt global.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+/// Number of memory accesses of one kind, by how they were handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct AccessStats {
+    /// Checked at runtime.
+    pub checked: u32,
+    /// Proven safe statically, not checked.
+    pub elided: u32,
+    /// Accesses to the stack, not checked.
+    pub stack: u32,
+    /// Accesses to exempt globals, not checked.
+    pub exempt: u32,
+}
+
+impl AccessStats {
+
+    /// Returns the number of accesses.
+    pub fn total(&self) -> u32 {
+        self.checked + self.elided + self.stack + self.exempt
+    }
+
+}
+
+/// Statistics of the instrumentation of a function.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct InstrumentationStats {
+    /// Name of the instrumented function.
+    pub function: String,
+    /// Statistics of each kind of access (`load`, `store`, `atomicrmw`, ...).
+    pub accesses: BTreeMap<String, AccessStats>,
+}
+
+impl InstrumentationStats {
+
+    /// Creates empty statistics for a function.
+    pub fn new(function: &str) -> Self {
+        InstrumentationStats {
+            function: function.to_string(),
+            accesses: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the statistics of a kind of access, to update them.
+    pub fn kind(&mut self, kind: &str) -> &mut AccessStats {
+        self.accesses.entry(kind.to_string()).or_default()
+    }
+
+    /// Returns the statistics of all the kinds of access.
+    pub fn total(&self) -> AccessStats {
+
+        let mut total = AccessStats::default();
+
+        for stats in self.accesses.values() {
+            total.checked += stats.checked;
+            total.elided += stats.elided;
+            total.stack += stats.stack;
+            total.exempt += stats.exempt;
+        }
+
+        total
+
+    }
+
+    /// Serialises the statistics of several functions as JSON.
+    pub fn to_json(stats: &[InstrumentationStats]) -> Result<String, String> {
+        serde_json::to_string_pretty(stats).map_err(|e| format!("Failed to serialise statistics: {}", e))
+    }
+
+}
+
+impl fmt::Display for InstrumentationStats {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        writeln!(f, "{}:", self.function)?;
+        writeln!(f, "  {:<14}{:>8}{:>8}{:>8}{:>8}{:>8}", "kind", "total", "checked", "elided", "stack", "exempt")?;
+
+        let rows = self.accesses.iter().map(|(kind, stats)| (kind.as_str(), *stats));
+
+        for (kind, stats) in rows.chain([("total", self.total())]) {
+            writeln!(f, "  {:<14}{:>8}{:>8}{:>8}{:>8}{:>8}", kind, stats.total(), stats.checked, stats.elided, stats.stack, stats.exempt)?;
+        }
+
+        Ok(())
+
+    }
+
+}
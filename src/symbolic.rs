@@ -0,0 +1,441 @@
+//! Symbolic model of pointers, used to reason about accesses relative to a
+//! protected region whose address is not constant.
+//!
+//! A pointer is modelled as `base + a_1 * x_1 + ... + a_n * x_n + c` bytes,
+//! where `base` is the pointer a chain of `getelementptr` instructions starts
+//! from, the `x_i` are integer SSA values (e.g. the `index` argument) and the
+//! `a_i` and `c` are constants.  Two pointers with the same base can then be
+//! compared whatever the value of the variables: `&shared_array[index + 1]` is
+//! always 8 bytes after `&shared_array[index]`.  When the distance between
+//! them still depends on variables, it is bounded with the known ranges of the
+//! variables (see `ranges`).
+//!
+//! Offsets follow the layout of the indexed types given by the target data:
+//! array and vector elements are `index * element size` bytes apart, and
+//! struct fields are at the offsets of the struct layout, e.g. `ring.buf[3]`
+//! is `16 + 3` bytes after `ring` in `struct ring { uint64_t head, tail;
+//! uint8_t buf[256]; }`.
+
+use inkwell::targets::TargetData;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{AsValueRef, InstructionValue, IntValue, PointerValue};
+use inkwell::values::BasicValueEnum::IntValue as IV;
+use inkwell::values::InstructionOpcode::{Add, Sub, Mul, Shl, GetElementPtr};
+use llvm::LLVMTypeKind::{LLVMIntegerTypeKind, LLVMPointerTypeKind};
+
+extern crate llvm_sys as llvm;
+
+use crate::ranges::{self, Ranges};
+
+/// Result of the static analysis of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The access is inside the protected region.
+    Proven,
+    /// The access is outside the protected region.
+    Refuted,
+    /// The analysis cannot decide, the access must be checked at runtime.
+    Unknown,
+}
+
+/// An affine expression `a_1 * x_1 + ... + a_n * x_n + c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AffineExpr<'a> {
+    /// The variables `x_i` with their coefficient `a_i`, which is never zero.
+    pub terms: Vec<(IntValue<'a>, i64)>,
+    /// The constant `c`.
+    pub constant: i64,
+}
+
+impl<'a> AffineExpr<'a> {
+
+    /// Creates the constant expression `c`.
+    pub fn constant(constant: i64) -> Self {
+        AffineExpr { terms: Vec::new(), constant }
+    }
+
+    /// Creates the expression `x`.
+    pub fn variable(variable: IntValue<'a>) -> Self {
+        AffineExpr { terms: vec![(variable, 1)], constant: 0 }
+    }
+
+    /// Returns the value of the expression if it has no variables.
+    pub fn as_constant(&self) -> Option<i64> {
+        if self.terms.is_empty() { Some(self.constant) } else { None }
+    }
+
+    /// Computes `self + other`, or `None` on overflow.
+    pub fn checked_add(&self, other: &AffineExpr<'a>) -> Option<Self> {
+
+        let mut terms = self.terms.clone();
+
+        for (variable, coefficient) in &other.terms {
+            match terms.iter().position(|(v, _)| v == variable) {
+                Some(i) => terms[i].1 = terms[i].1.checked_add(*coefficient)?,
+                None => terms.push((*variable, *coefficient)),
+            }
+        }
+
+        terms.retain(|(_, coefficient)| *coefficient != 0);
+
+        Some(AffineExpr { terms, constant: self.constant.checked_add(other.constant)? })
+
+    }
+
+    /// Computes `self - other`, or `None` on overflow.
+    pub fn checked_sub(&self, other: &AffineExpr<'a>) -> Option<Self> {
+        self.checked_add(&other.checked_mul(-1)?)
+    }
+
+    /// Computes `k * self`, or `None` on overflow.
+    pub fn checked_mul(&self, k: i64) -> Option<Self> {
+
+        let mut terms = Vec::new();
+
+        for (variable, coefficient) in &self.terms {
+            terms.push((*variable, coefficient.checked_mul(k)?));
+        }
+
+        terms.retain(|(_, coefficient)| *coefficient != 0);
+
+        Some(AffineExpr { terms, constant: self.constant.checked_mul(k)? })
+
+    }
+
+    /// Computes the range of values of the expression, given the known ranges
+    /// of its variables.
+    pub fn get_range(&self, ranges: &Ranges) -> (i128, i128) {
+
+        let mut min = self.constant as i128;
+        let mut max = self.constant as i128;
+
+        for (variable, coefficient) in &self.terms {
+
+            let (variable_min, variable_max) = ranges::get_range(ranges, *variable);
+            let bounds = (*coefficient as i128 * variable_min as i128, *coefficient as i128 * variable_max as i128);
+
+            min += bounds.0.min(bounds.1);
+            max += bounds.0.max(bounds.1);
+
+        }
+
+        (min, max)
+
+    }
+
+}
+
+/// A pointer `base + offset` bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolicPointer<'a> {
+    pub base: PointerValue<'a>,
+    pub offset: AffineExpr<'a>,
+}
+
+fn _get_int_operand(instr: InstructionValue, index: u32) -> Option<IntValue> {
+
+    match instr.get_operand(index)?.left()? {
+        IV(value) => Some(value),
+        _ => None,
+    }
+
+}
+
+fn _decompose<'a>(target_data: &TargetData, value: IntValue<'a>) -> Option<AffineExpr<'a>> {
+
+    // Narrower integers wrap before being extended to the pointer width,
+    // so only pointer-sized arithmetic matches address arithmetic
+    if value.get_type().get_bit_width() != target_data.get_pointer_byte_size(None) * 8 {
+        return None;
+    }
+
+    let instr = value.as_instruction()?;
+
+    if ![Add, Sub, Mul, Shl].contains(&instr.get_opcode()) {
+        return None;
+    }
+
+    let lhs = get_affine_expr(target_data, _get_int_operand(instr, 0)?);
+    let rhs = get_affine_expr(target_data, _get_int_operand(instr, 1)?);
+
+    match instr.get_opcode() {
+        Add => lhs.checked_add(&rhs),
+        Sub => lhs.checked_sub(&rhs),
+        Mul => match (lhs.as_constant(), rhs.as_constant()) {
+            (Some(k), _) => rhs.checked_mul(k),
+            (_, Some(k)) => lhs.checked_mul(k),
+            _ => None,
+        },
+        Shl => match rhs.as_constant() {
+            Some(k) if (0..63).contains(&k) => lhs.checked_mul(1 << k),
+            _ => None,
+        },
+        _ => None,
+    }
+
+}
+
+/// Decomposes an integer value into an affine expression.
+///
+/// Additions, subtractions, and multiplications and shifts by a constant are
+/// decomposed; any other value is a variable of the expression.
+pub fn get_affine_expr<'a>(target_data: &TargetData, value: IntValue<'a>) -> AffineExpr<'a> {
+
+    if let Some(constant) = value.get_sign_extended_constant() {
+        return AffineExpr::constant(constant);
+    }
+
+    _decompose(target_data, value).unwrap_or_else(|| AffineExpr::variable(value))
+
+}
+
+/// Checks that the arithmetic decomposed by [`get_affine_expr`] cannot wrap,
+/// i.e. every decomposed instruction has the `nuw` or `nsw` flag.
+fn _has_no_wrap(target_data: &TargetData, value: IntValue) -> bool {
+
+    // Constants and variables are their own value
+    if value.get_sign_extended_constant().is_some() || _decompose(target_data, value).is_none() {
+        return true;
+    }
+
+    let Some(instr) = value.as_instruction() else {
+        return true;
+    };
+
+    let no_wrap = unsafe {
+        llvm::core::LLVMGetNUW(instr.as_value_ref()) != 0 || llvm::core::LLVMGetNSW(instr.as_value_ref()) != 0
+    };
+
+    no_wrap && [0, 1].iter().all(|&i| match _get_int_operand(instr, i) {
+        Some(operand) => _has_no_wrap(target_data, operand),
+        None => true,
+    })
+
+}
+
+/// Decomposes the length of a protected region into an affine expression.
+///
+/// The IR arithmetic wraps modulo 2^64, so the expression is the length only
+/// if it cannot overflow: `n * 8` wraps to 0 for `n = 2^61`.  This holds when
+/// the arithmetic has the `nuw` or `nsw` flag, or when the known ranges of the
+/// variables keep the expression between 0 and 2^64 - 1.
+///
+/// # Returns
+///
+/// Returns `None` if the length may have wrapped.
+pub fn get_length_expr<'a>(target_data: &TargetData, len: IntValue<'a>, ranges: &Ranges) -> Option<AffineExpr<'a>> {
+
+    let expr = get_affine_expr(target_data, len);
+
+    if _has_no_wrap(target_data, len) {
+        return Some(expr);
+    }
+
+    let (min, max) = expr.get_range(ranges);
+
+    if min >= 0 && max <= u64::MAX as i128 { Some(expr) } else { None }
+
+}
+
+/// Returns the source element type, the base pointer and the indices of a
+/// `getelementptr`, either an instruction or a constant expression, e.g.
+/// `getelementptr inbounds (%struct.ring, ptr @ring, i64 0, i32 2, i64 3)`.
+fn _get_gep<'a>(ptr: PointerValue<'a>) -> Option<(BasicTypeEnum<'a>, PointerValue<'a>, Vec<IntValue<'a>>)> {
+
+    let value = ptr.as_value_ref();
+
+    let is_gep = match ptr.as_instruction() {
+        Some(instr) => instr.get_opcode() == GetElementPtr,
+        None => unsafe {
+            !llvm::core::LLVMIsAConstantExpr(value).is_null()
+                && llvm::core::LLVMGetConstOpcode(value) == llvm::LLVMOpcode::LLVMGetElementPtr
+        },
+    };
+
+    if !is_gep {
+        return None;
+    }
+
+    unsafe {
+
+        let source_type = BasicTypeEnum::new(llvm::core::LLVMGetGEPSourceElementType(value));
+
+        let base = llvm::core::LLVMGetOperand(value, 0);
+        if llvm::core::LLVMGetTypeKind(llvm::core::LLVMTypeOf(base)) != LLVMPointerTypeKind {
+            return None;
+        }
+
+        let mut indices: Vec<IntValue> = Vec::new();
+
+        for i in 1..llvm::core::LLVMGetNumOperands(value) as u32 {
+            let index = llvm::core::LLVMGetOperand(value, i);
+            if llvm::core::LLVMGetTypeKind(llvm::core::LLVMTypeOf(index)) != LLVMIntegerTypeKind {
+                return None;
+            }
+            indices.push(IntValue::new(index));
+        }
+
+        Some((source_type, PointerValue::new(base), indices))
+
+    }
+
+}
+
+/// Computes the byte offset of a `getelementptr` from its base, given its
+/// source element type and its indices.
+fn _get_gep_offset<'a>(target_data: &TargetData, source_type: BasicTypeEnum<'a>, indices: &[IntValue<'a>]) -> Option<AffineExpr<'a>> {
+
+    let mut element_type = source_type;
+
+    let mut offset = AffineExpr::constant(0);
+
+    for (i, index) in indices.iter().enumerate() {
+
+        // The first index steps over whole source elements,
+        // the next ones step into the current element
+        if i > 0 {
+
+            // Struct fields are at the offsets of the struct layout, their index is constant
+            if let BasicTypeEnum::StructType(struct_type) = element_type {
+
+                let field = index.get_zero_extended_constant()? as u32;
+                if field >= struct_type.count_fields() {
+                    return None;
+                }
+
+                let field_offset = target_data.offset_of_element(&struct_type, field)? as i64;
+
+                offset = offset.checked_add(&AffineExpr::constant(field_offset))?;
+                element_type = struct_type.get_field_type_at_index(field)?;
+
+                continue;
+
+            }
+
+            element_type = match element_type {
+                BasicTypeEnum::ArrayType(array_type) => array_type.get_element_type(),
+                BasicTypeEnum::VectorType(vector_type) => vector_type.get_element_type(),
+                _ => return None,
+            };
+
+        }
+
+        let element_size = target_data.get_abi_size(&element_type) as i64;
+
+        offset = offset.checked_add(&get_affine_expr(target_data, *index).checked_mul(element_size)?)?;
+
+    }
+
+    Some(offset)
+
+}
+
+/// Models a pointer as its base plus an affine byte offset, following
+/// `getelementptr` instructions and constant expressions back to their base
+/// pointer.
+///
+/// # Returns
+///
+/// Returns `None` if an offset cannot be modelled (e.g. vector indices).
+pub fn get_symbolic_pointer<'a>(target_data: &TargetData, ptr: PointerValue<'a>) -> Option<SymbolicPointer<'a>> {
+
+    let mut base = ptr;
+    let mut offset = AffineExpr::constant(0);
+
+    while let Some((source_type, gep_base, indices)) = _get_gep(base) {
+
+        offset = offset.checked_add(&_get_gep_offset(target_data, source_type, &indices)?)?;
+
+        base = gep_base;
+
+    }
+
+    Some(SymbolicPointer { base, offset })
+
+}
+
+fn _is_global_variable(ptr: PointerValue) -> bool {
+    unsafe { !llvm::core::LLVMIsAGlobalVariable(ptr.as_value_ref()).is_null() }
+}
+
+/// Computes the distance in bytes from a protected pointer to an accessed
+/// pointer, if both are derived from the same base.
+///
+/// # Returns
+///
+/// Returns the distance, or the verdict when it cannot be computed:
+/// [`Verdict::Refuted`] if the pointers are derived from distinct global
+/// variables, which never overlap, and [`Verdict::Unknown`] otherwise.
+pub fn get_distance<'a>(target_data: &TargetData, protected_ptr: PointerValue<'a>, ptr: PointerValue<'a>) -> Result<AffineExpr<'a>, Verdict> {
+
+    let (Some(region), Some(access)) = (get_symbolic_pointer(target_data, protected_ptr), get_symbolic_pointer(target_data, ptr)) else {
+        return Err(Verdict::Unknown);
+    };
+
+    if region.base != access.base {
+
+        // Distinct global variables never overlap
+        if _is_global_variable(region.base) && _is_global_variable(access.base) {
+            return Err(Verdict::Refuted);
+        }
+
+        return Err(Verdict::Unknown);
+
+    }
+
+    access.offset.checked_sub(&region.offset).ok_or(Verdict::Unknown)
+
+}
+
+/// Checks symbolically if an access is inside a protected region.
+///
+/// # Arguments
+///
+/// * `target_data` - The target data of the module, used to compute offsets.
+/// * `protected_mem` - The protected region, as its pointer and length.  The
+///   length is modelled like the offsets when it cannot wrap (see
+///   [`get_length_expr`]), so that `buf[n - 1]` is proven inside a region of
+///   `n * 8` bytes at `buf` for every `n` below 2^32.
+/// * `ptr` - The accessed pointer.
+/// * `size` - The number of bytes accessed.
+/// * `ranges` - The known ranges of integer values where the access happens.
+///
+/// # Returns
+///
+/// Returns [`Verdict::Proven`] if the access is inside the region for every
+/// value of the variables, [`Verdict::Refuted`] if it is outside for every
+/// value, and [`Verdict::Unknown`] otherwise.
+pub fn check_access(target_data: &TargetData, protected_mem: (PointerValue, IntValue), ptr: PointerValue, size: u64, ranges: &Ranges) -> Verdict {
+
+    let (protected_ptr, protected_len) = protected_mem;
+
+    // Distance in bytes between the start of the region and the access
+    let distance = match get_distance(target_data, protected_ptr, ptr) {
+        Ok(distance) => distance,
+        Err(verdict) => return verdict,
+    };
+
+    // A wrapped length is not the size of the region
+    let Some(protected_len) = get_length_expr(target_data, protected_len, ranges) else {
+        return Verdict::Unknown;
+    };
+
+    // Bytes of the region left after the access
+    let Some(slack) = protected_len
+        .checked_sub(&distance)
+        .and_then(|expr| expr.checked_sub(&AffineExpr::constant(size as i64))) else {
+        return Verdict::Unknown;
+    };
+
+    let (min_distance, max_distance) = distance.get_range(ranges);
+    let (min_slack, max_slack) = slack.get_range(ranges);
+
+    if min_distance >= 0 && min_slack >= 0 {
+        Verdict::Proven
+    } else if max_distance < 0 || max_slack < 0 {
+        Verdict::Refuted
+    } else {
+        Verdict::Unknown
+    }
+
+}
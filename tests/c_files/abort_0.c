@@ -0,0 +1,24 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void abort_0(uint64_t index)
+{
+	if (index >= 100)
+		abort();
+	utx1(&shared_array, sizeof (shared_array));
+	shared_array[index] += 1;
+}
+
+int main()
+{
+	abort_0(1);
+	return 0;
+}
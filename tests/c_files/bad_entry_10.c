@@ -0,0 +1,29 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+struct ring {
+	uint64_t head, tail;
+	uint8_t buf[256];
+};
+
+static struct ring ring;
+
+
+void bad_entry_10()
+{
+	utx1(&ring.buf, sizeof (ring.buf));
+	ring.buf[3] = 1;
+	// The field is outside of the protected buffer
+	ring.tail += 1;
+}
+
+int main()
+{
+	bad_entry_10();
+	return 0;
+}
@@ -0,0 +1,24 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_matrix[10][10];
+
+
+void bad_entry_11()
+{
+	utx1(&shared_matrix[2], sizeof (shared_matrix[2]));
+	shared_matrix[2][3] += 1;
+	// The row after the protected one
+	shared_matrix[3][0] = 0;
+}
+
+int main()
+{
+	bad_entry_11();
+	return 0;
+}
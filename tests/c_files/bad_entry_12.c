@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void bad_entry_12()
+{
+	// Only the first half of the array is protected
+	utx1(&shared_array, 50 * sizeof (shared_array[0]));
+	for (int i = 0; i < 100; i++) {
+		shared_array[i] = i;
+	}
+}
+
+int main()
+{
+	bad_entry_12();
+	return 0;
+}
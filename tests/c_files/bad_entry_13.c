@@ -0,0 +1,29 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+void bad_entry_13(uint64_t n)
+{
+	if (n > 1000) {
+		return;
+	}
+	uint64_t *buf = malloc((n + 1) * sizeof (buf[0]));
+	if (buf == NULL) {
+		return;
+	}
+	utx1(buf, (n + 1) * sizeof (buf[0]));
+	// One element past the end of the buffer
+	buf[n + 1] = 2;
+	utx0();
+	free(buf);
+}
+
+int main()
+{
+	bad_entry_13(10);
+	return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void bad_entry_7(uint64_t index)
+{
+	uint64_t expected = 0;
+
+	utx1(&shared_array[0], sizeof (shared_array[0]));
+	// Atomic access outside of the protected memory
+	__atomic_compare_exchange_n(&shared_array[index], &expected, 1, 0, __ATOMIC_SEQ_CST, __ATOMIC_SEQ_CST);
+}
+
+int main()
+{
+	bad_entry_7(7);
+	return 0;
+}
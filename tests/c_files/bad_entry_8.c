@@ -0,0 +1,28 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+// Vectorized with masked loads and stores (compiled with -mavx2)
+void bad_entry_8(uint64_t limit)
+{
+	// Only the first half of the array is protected
+	utx1(&shared_array, sizeof (shared_array) / 2);
+	for (int i = 0; i < 100; i++) {
+		if (shared_array[i] < limit) {
+			shared_array[i] = limit;
+		}
+	}
+}
+
+int main()
+{
+	bad_entry_8(7);
+	return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void bad_entry_9(uint64_t index)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	// The guard is off by one
+	if (index <= 100) {
+		shared_array[index] += 1;
+	}
+}
+
+int main()
+{
+	bad_entry_9(100);
+	return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+static const uint64_t table[4] = {3, 1, 4, 1};
+
+
+// The lookup table is outside of the protected region, but read only
+void const_0(uint64_t index)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	shared_array[1] += table[index & 3];
+}
+
+int main()
+{
+	const_0(5);
+	return 0;
+}
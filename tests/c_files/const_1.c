@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+static const uint64_t table[4] = {3, 1, 4, 1};
+
+
+// The lookup table is read only, but the index is not bounded
+void const_1(uint64_t index)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	shared_array[1] += table[index];
+}
+
+int main()
+{
+	const_1(4);
+	return 0;
+}
@@ -0,0 +1,26 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+static uint64_t config[8];
+
+
+void exempt_0(uint64_t index)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	shared_array[1] = config[2];
+	// The index is not bounded, it can reach any address past config
+	config[index] = 1;
+}
+
+int main()
+{
+	exempt_0(3);
+	return 0;
+}
@@ -0,0 +1,27 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+// Vectorized with a gather (compiled with -mavx512f)
+uint64_t gather_0(const uint32_t *indices)
+{
+	uint64_t sum = 0;
+	utx1(&shared_array, sizeof (shared_array));
+	for (int i = 0; i < 64; i++) {
+		sum += shared_array[indices[i]];
+	}
+	return sum;
+}
+
+int main()
+{
+	uint32_t indices[64] = { 0 };
+	return gather_0(indices);
+}
@@ -0,0 +1,23 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void good_entry_10(uint64_t index)
+{
+	// Protects two elements starting from a dynamic index
+	utx1(&shared_array[index], 2 * sizeof (shared_array[index]));
+	shared_array[index+1] += 1;
+}
+
+int main()
+{
+	good_entry_10(7);
+	return 0;
+}
@@ -0,0 +1,26 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void good_entry_11(uint64_t index)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	// The access is only reached if the guard is false
+	if (index >= 100) {
+		return;
+	}
+	shared_array[index] += 1;
+}
+
+int main()
+{
+	good_entry_11(99);
+	return 0;
+}
@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void good_entry_12(uint64_t i, uint64_t j)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	// i is only bounded through j
+	if (j < 100 && i < j) {
+		shared_array[i] += 1;
+	}
+}
+
+int main()
+{
+	good_entry_12(7, 8);
+	return 0;
+}
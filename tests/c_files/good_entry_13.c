@@ -0,0 +1,31 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+struct ring {
+	uint64_t head, tail;
+	uint8_t buf[256];
+};
+
+static struct ring ring;
+
+
+void good_entry_13(uint64_t index)
+{
+	utx1(&ring, sizeof (ring));
+	ring.tail += 1;
+	ring.buf[3] = 1;
+	if (index < 256) {
+		ring.buf[index] = 2;
+	}
+}
+
+int main()
+{
+	good_entry_13(1);
+	return 0;
+}
@@ -0,0 +1,23 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_matrix[10][10];
+
+
+void good_entry_14()
+{
+	utx1(&shared_matrix[2], sizeof (shared_matrix[2]));
+	shared_matrix[2][3] += 1;
+	shared_matrix[2][9] = 0;
+}
+
+int main()
+{
+	good_entry_14();
+	return 0;
+}
@@ -0,0 +1,23 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[4];
+
+
+void good_entry_15(uint64_t *buf)
+{
+	utx1(buf, 4 * sizeof (buf[0]));
+	buf[0] = 1;
+	buf[3] += 1;
+}
+
+int main()
+{
+	good_entry_15(shared_array);
+	return 0;
+}
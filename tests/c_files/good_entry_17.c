@@ -0,0 +1,27 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+void good_entry_17(uint32_t n)
+{
+	// The length cannot wrap, n is only bounded by its type
+	uint64_t *buf = malloc(((uint64_t) n + 1) * sizeof (buf[0]));
+	if (buf == NULL) {
+		return;
+	}
+	utx1(buf, ((uint64_t) n + 1) * sizeof (buf[0]));
+	buf[0] = 1;
+	buf[n] = 2;
+	utx0();
+	free(buf);
+}
+
+int main()
+{
+	good_entry_17(10);
+	return 0;
+}
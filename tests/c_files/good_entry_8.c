@@ -0,0 +1,25 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void good_entry_8()
+{
+	uint64_t expected = 0;
+
+	utx1(&shared_array, sizeof (shared_array));
+	__atomic_fetch_add(&shared_array[1], 1, __ATOMIC_SEQ_CST);
+	__atomic_compare_exchange_n(&shared_array[2], &expected, 1, 0, __ATOMIC_SEQ_CST, __ATOMIC_SEQ_CST);
+}
+
+int main()
+{
+	good_entry_8();
+	return 0;
+}
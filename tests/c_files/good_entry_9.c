@@ -0,0 +1,27 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+// Vectorized with masked loads and stores (compiled with -mavx2)
+void good_entry_9(uint64_t limit)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	for (int i = 0; i < 100; i++) {
+		if (shared_array[i] < limit) {
+			shared_array[i] = limit;
+		}
+	}
+}
+
+int main()
+{
+	good_entry_9(7);
+	return 0;
+}
@@ -0,0 +1,23 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+// The region and the access are both at an address computed as an integer
+void int_to_ptr_0(uintptr_t addr)
+{
+	utx1((void *) addr, sizeof (uint64_t));
+	*(uint64_t *) addr += 1;
+}
+
+int main()
+{
+	int_to_ptr_0((uintptr_t) &shared_array[1]);
+	return 0;
+}
@@ -0,0 +1,26 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+// Vectorized with a scatter (compiled with -mavx512f)
+void scatter_0(const uint32_t *indices)
+{
+	utx1(&shared_array, sizeof (shared_array));
+	for (int i = 0; i < 64; i++) {
+		shared_array[indices[i]] = i;
+	}
+}
+
+int main()
+{
+	uint32_t indices[64] = { 0 };
+	scatter_0(indices);
+	return 0;
+}
@@ -0,0 +1,28 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t shared_array[100];
+
+
+void stack_1(uint64_t index)
+{
+	uint64_t buf[16];
+
+	utx1(&shared_array, sizeof (shared_array));
+	for (int i = 0; i < 16; i++) {
+		buf[i] = i * index;
+	}
+	// Dynamic index in a stack array, out of bounds if index >= 16
+	shared_array[0] = buf[index];
+}
+
+int main()
+{
+	stack_1(20);
+	return 0;
+}
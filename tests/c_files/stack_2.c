@@ -0,0 +1,26 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+static uint64_t *shared_array[100];
+static uint64_t *outside;
+
+
+void stack_2(uint64_t **slot)
+{
+	uint64_t local = 0;
+
+	utx1(&shared_array, sizeof (shared_array));
+	// Stores the address of a stack slot, through a pointer outside the region
+	*slot = &local;
+}
+
+int main()
+{
+	stack_2(&outside);
+	return 0;
+}
@@ -0,0 +1,30 @@
+#include <stdint.h>
+#include <stdlib.h>
+
+
+void utx0(void);
+void utx1(void *addr, size_t len);
+
+
+void wrap_0(uint64_t n)
+{
+	if (n == 0) {
+		return;
+	}
+	// The length wraps to 0 for n = 2^61
+	uint64_t *buf = malloc(n * sizeof (buf[0]));
+	if (buf == NULL) {
+		return;
+	}
+	utx1(buf, n * sizeof (buf[0]));
+	// 8 bytes before the buffer for n = 2^61
+	buf[n - 1] = 2;
+	utx0();
+	free(buf);
+}
+
+int main()
+{
+	wrap_0((uint64_t) 1 << 61);
+	return 0;
+}
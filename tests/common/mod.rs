@@ -0,0 +1,63 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses a part of the helpers
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::Command;
+use inkwell::context::Context;
+use inkwell::memory_buffer::MemoryBuffer;
+use inkwell::module::Module;
+
+/// Compile testcases' C sources.
+pub fn compile_c_files() {
+    let c_files_dir_path = Path::new("tests/c_files/");
+
+    // Compile the C source file
+    Command::new("make")
+        .args(["-C", &c_files_dir_path.to_string_lossy()])
+        .output()
+        .expect("Failed to compile C source file.");
+}
+
+/// Parse a testcase: its LLVM IR if it is written in IR, or its bitcode,
+/// compiling the C sources if needed.
+pub fn load_testcase<'a>(context: &'a Context, testcase_name: &str) -> Module<'a> {
+
+    let ll_path = format!("tests/ll_files/{}.ll", testcase_name);
+    let ll_path = Path::new(&ll_path);
+
+    if ll_path.exists() {
+        let buffer = MemoryBuffer::create_from_file(ll_path).unwrap();
+        return context.create_module_from_ir(buffer).unwrap();
+    }
+
+    // Get testcase bitcode path
+    let bitcode_path = format!("target/tests/{}.bc", testcase_name);
+    let bitcode_path = Path::new(&bitcode_path);
+
+    // Compile if it not exists
+    if !bitcode_path.exists() {
+        compile_c_files();
+    }
+
+    Module::parse_bitcode_from_path(&bitcode_path, context).unwrap()
+}
+
+/// Compile instrumented LLVMs to executable
+pub fn compile_ll_to_exec(filepath: &str) -> String {
+    let filepath = Path::new(filepath);
+    let exec_filepath = filepath.with_extension("o");
+
+    // Compile
+    let output = Command::new("clang")
+        .arg(filepath)
+        .arg("-o")
+        .arg(exec_filepath.clone())
+        .output()
+        .expect("Failed to compile LLVMs to executable.");
+
+    println!("{:?}", output);
+
+    exec_filepath.display().to_string()
+}
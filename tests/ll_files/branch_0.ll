@@ -0,0 +1,18 @@
+; Both edges of the conditional branch lead to %body: the comparison does not
+; bound %index there.
+
+@shared_array = internal global [100 x i64] zeroinitializer
+
+declare void @utx1(ptr, i64)
+
+define void @branch_0(i64 %index) {
+entry:
+  call void @utx1(ptr @shared_array, i64 800)
+  %in_bounds = icmp ult i64 %index, 100
+  br i1 %in_bounds, label %body, label %body
+
+body:
+  %ptr = getelementptr inbounds [100 x i64], ptr @shared_array, i64 0, i64 %index
+  store i64 1, ptr %ptr, align 8
+  ret void
+}
@@ -0,0 +1,26 @@
+; The call to rand inside the transaction is an invoke, as in C++ code that
+; may throw.
+
+@shared_array = internal global [100 x i64] zeroinitializer
+
+declare void @utx0()
+declare void @utx1(ptr, i64)
+declare i32 @rand()
+declare i32 @__gxx_personality_v0(...)
+
+define void @invoke_0() personality ptr @__gxx_personality_v0 {
+entry:
+  call void @utx1(ptr @shared_array, i64 800)
+  %value = invoke i32 @rand()
+          to label %continue unwind label %cleanup
+
+continue:
+  %value64 = sext i32 %value to i64
+  store i64 %value64, ptr getelementptr inbounds ([100 x i64], ptr @shared_array, i64 0, i64 1), align 8
+  call void @utx0()
+  ret void
+
+cleanup:
+  %landing = landingpad { ptr, i32 } cleanup
+  resume { ptr, i32 } %landing
+}
@@ -0,0 +1,20 @@
+; Both edges of the latch branch back to the header: the exit condition does
+; not bound %i, the loop never ends.
+
+@shared_array = internal global [100 x i64] zeroinitializer
+
+declare void @utx1(ptr, i64)
+
+define void @loop_0() {
+entry:
+  call void @utx1(ptr @shared_array, i64 800)
+  br label %loop
+
+loop:
+  %i = phi i64 [ 0, %entry ], [ %i.next, %loop ]
+  %ptr = getelementptr inbounds [100 x i64], ptr @shared_array, i64 0, i64 %i
+  store i64 1, ptr %ptr, align 8
+  %i.next = add nuw nsw i64 %i, 1
+  %done = icmp eq i64 %i.next, 100
+  br i1 %done, label %loop, label %loop
+}
@@ -0,0 +1,28 @@
+; The first store of the loop body splits its block, which rebuilds the phi
+; of the loop index, the second store is still bounded by the loop condition.
+
+@shared_array = internal global [100 x i64] zeroinitializer
+
+declare void @utx1(ptr, i64)
+
+define void @phi_1(ptr %other) {
+entry:
+  call void @utx1(ptr @shared_array, i64 800)
+  br label %header
+
+header:
+  %i = phi i64 [ 0, %entry ], [ %i.next, %body ]
+  %in_bounds = icmp ult i64 %i, 100
+  br i1 %in_bounds, label %body, label %exit
+
+body:
+  %other_ptr = getelementptr inbounds i64, ptr %other, i64 %i
+  store i64 %i, ptr %other_ptr, align 8
+  %ptr = getelementptr inbounds [100 x i64], ptr @shared_array, i64 0, i64 %i
+  store i64 %i, ptr %ptr, align 8
+  %i.next = add nuw nsw i64 %i, 1
+  br label %header
+
+exit:
+  ret void
+}
@@ -0,0 +1,12 @@
+; Both stores are at a constant offset from the stack array, only the first
+; one is inside it: `buf[20]` on `uint64_t buf[16]`.
+
+define void @stack_3() {
+entry:
+  %buf = alloca [16 x i64], align 16
+  %inside = getelementptr inbounds [16 x i64], ptr %buf, i64 0, i64 3
+  store volatile i64 1, ptr %inside, align 8
+  %outside = getelementptr inbounds [16 x i64], ptr %buf, i64 0, i64 20
+  store volatile i64 2, ptr %outside, align 8
+  ret void
+}
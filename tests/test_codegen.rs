@@ -0,0 +1,172 @@
+mod common;
+
+use llvm_sandboxer::{codegen, runtime};
+
+use std::path::Path;
+use std::process::Command;
+use inkwell::context::Context;
+
+use common::{compile_c_files, compile_ll_to_exec, load_testcase};
+
+/// Instrument and optimise one LLVM bitcode file.
+fn instrument_and_optimize_testcase(testcase_name: &str, opt_level: u32) -> String {
+    let context = Context::create();
+    let module = load_testcase(&context, testcase_name);
+
+    runtime::instrument(testcase_name, &context, &module, true).unwrap();
+
+    // Optimise
+    let target_machine = codegen::create_target_machine(None, None, opt_level).unwrap();
+    codegen::optimize(&module, &target_machine, opt_level).unwrap();
+    assert!(module.verify().is_ok());
+
+    // Save to file
+    let filepath = format!("target/tests/instrumented/{}_O{}_instrumented.ll", testcase_name, opt_level);
+    let _ = module.print_to_file(filepath.clone());
+
+    return filepath
+}
+
+/// Instrument one LLVM bitcode file and compile it to an executable with
+/// `codegen`, without `clang`.
+fn instrument_testcase_to_exec(testcase_name: &str) -> String {
+    let context = Context::create();
+    let module = load_testcase(&context, testcase_name);
+
+    runtime::instrument(testcase_name, &context, &module, true).unwrap();
+
+    // Emit the object file and link it
+    let object_path = format!("target/tests/instrumented/{}_codegen.o", testcase_name);
+    let exec_path = format!("target/tests/instrumented/{}_codegen", testcase_name);
+    let target_machine = codegen::create_target_machine(None, None, 0).unwrap();
+    codegen::write_object_file(&module, &target_machine, Path::new(&object_path)).unwrap();
+    codegen::link_executable(Path::new(&object_path), Path::new(&exec_path)).unwrap();
+
+    return exec_path
+}
+
+#[test]
+fn test_optimize_bad_entry_0() {
+    let ll_filepath = instrument_and_optimize_testcase("bad_entry_0", 2);
+    let filepath = compile_ll_to_exec(&ll_filepath);
+
+    // Execute the optimised testcase
+    let output = Command::new(filepath.clone())
+        .output()
+        .expect(&format!("Cannot execute {}.", filepath));
+
+    // Check that the checks were not optimised away
+    assert_eq!(output.status.code(), None);
+}
+
+#[test]
+fn test_optimize_good_entry_0() {
+    let ll_filepath = instrument_and_optimize_testcase("good_entry_0", 2);
+    let filepath = compile_ll_to_exec(&ll_filepath);
+
+    // Execute the optimised testcase
+    let output = Command::new(filepath.clone())
+        .output()
+        .expect(&format!("Cannot execute {}.", filepath));
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_optimize_invalid_level() {
+    assert!(codegen::create_target_machine(None, None, 4).is_err());
+}
+
+#[test]
+fn test_invalid_target() {
+    assert!(codegen::create_target_machine(Some("not-a-target"), None, 0).is_err());
+}
+
+#[test]
+fn test_optimize_sets_target() {
+    let context = Context::create();
+    let module = context.create_module("empty");
+    let target_machine = codegen::create_target_machine(None, None, 2).unwrap();
+
+    codegen::optimize(&module, &target_machine, 2).unwrap();
+
+    // The passes ran with the triple and data layout of the target
+    assert_eq!(module.get_triple(), target_machine.get_triple());
+    assert_eq!(module.get_data_layout().as_str(), target_machine.get_target_data().get_data_layout().as_str());
+}
+
+#[test]
+fn test_emit_exe_bad_entry_0() {
+    let filepath = instrument_testcase_to_exec("bad_entry_0");
+
+    // Execute the instrumented testcase
+    let output = Command::new(filepath.clone())
+        .output()
+        .expect(&format!("Cannot execute {}.", filepath));
+
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+#[test]
+fn test_emit_exe_good_entry_0() {
+    let filepath = instrument_testcase_to_exec("good_entry_0");
+
+    // Execute the instrumented testcase
+    let output = Command::new(filepath.clone())
+        .output()
+        .expect(&format!("Cannot execute {}.", filepath));
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_emit_obj_refuted_bad_entry_4() {
+    let bitcode_path = Path::new("target/tests/bad_entry_4.bc");
+    if !bitcode_path.exists() {
+        compile_c_files();
+    }
+    let object_path = Path::new("target/tests/instrumented/bad_entry_4_cli.o");
+    let _ = std::fs::remove_file(object_path);
+
+    // The access is proven outside the protected region, so nothing is emitted
+    let output = Command::new(env!("CARGO_BIN_EXE_llvm_sandboxer"))
+        .arg(bitcode_path)
+        .args(["bad_entry_4", "-s", "--emit-obj"])
+        .arg(object_path)
+        .output()
+        .expect("Cannot execute llvm_sandboxer.");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Access outside the protected region"));
+    assert!(!object_path.exists());
+}
+
+/// With both `--emit-obj` and `--emit-exe`, the object file is kept and linked.
+#[test]
+fn test_emit_obj_and_exe_good_entry_0() {
+    let bitcode_path = Path::new("target/tests/good_entry_0.bc");
+    if !bitcode_path.exists() {
+        compile_c_files();
+    }
+    let object_path = Path::new("target/tests/instrumented/good_entry_0_cli.o");
+    let exec_path = Path::new("target/tests/instrumented/good_entry_0_cli");
+    let _ = std::fs::remove_file(object_path);
+    let _ = std::fs::remove_file(exec_path);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_llvm_sandboxer"))
+        .arg(bitcode_path)
+        .args(["good_entry_0", "-s", "--emit-obj"])
+        .arg(object_path)
+        .arg("--emit-exe")
+        .arg(exec_path)
+        .output()
+        .expect("Cannot execute llvm_sandboxer.");
+
+    println!("{:?}", output);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(object_path.exists());
+    assert!(exec_path.exists());
+}
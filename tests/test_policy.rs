@@ -0,0 +1,49 @@
+use llvm_sandboxer::policy::{CallPolicy, Markers, Policy};
+
+use std::path::Path;
+
+/// Missing fields take their default value.
+#[test]
+fn test_empty_policy() {
+    assert_eq!(Policy::from_toml("").unwrap(), Policy::default());
+}
+
+#[test]
+fn test_policy_file() {
+    let policy = Policy::from_file(Path::new("tests/policies/strict.toml")).unwrap();
+
+    assert_eq!(policy, Policy {
+        markers: Markers {
+            begin: "utx1".to_string(),
+            end: "utx0".to_string(),
+        },
+        violation_handler: "abort".to_string(),
+        exempt_globals: vec!["config".to_string()],
+        calls: CallPolicy::Allow(vec!["rand".to_string()]),
+        static_analysis: true,
+        check_stack_bounds: true,
+        diagnostics: false,
+        read_constant_globals: false,
+    });
+}
+
+#[test]
+fn test_call_policy() {
+    let policy = Policy::from_toml("calls = { deny = [\"rand\"] }").unwrap();
+    assert_eq!(policy.calls, CallPolicy::Deny(vec!["rand".to_string()]));
+
+    let policy = Policy::from_toml("calls = \"allow_all\"").unwrap();
+    assert_eq!(policy.calls, CallPolicy::AllowAll);
+}
+
+#[test]
+fn test_read_constant_globals() {
+    let policy = Policy::from_toml("read_constant_globals = true").unwrap();
+    assert!(policy.read_constant_globals);
+    assert!(!Policy::default().read_constant_globals);
+}
+
+#[test]
+fn test_unknown_field() {
+    assert!(Policy::from_toml("static_analyis = true").is_err());
+}
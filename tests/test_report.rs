@@ -0,0 +1,129 @@
+mod common;
+
+use llvm_sandboxer::static_checks;
+use llvm_sandboxer::policy::{CallPolicy, Markers, Policy};
+use llvm_sandboxer::report::{SourceLocation, VerificationReport, Violation, ViolationKind};
+
+use inkwell::context::Context;
+
+use common::load_testcase;
+
+/// Verify one LLVM bitcode file and report its violations.
+fn report_testcase(testcase_name: &str, policy: &Policy) -> VerificationReport {
+    let context = Context::create();
+    let module = load_testcase(&context, testcase_name);
+    let function = module.get_function(testcase_name).unwrap();
+    VerificationReport { violations: static_checks::get_violations(module, function, policy) }
+}
+
+#[test]
+fn test_report_good_entry_0() {
+    let report = report_testcase("good_entry_0", &Policy::default());
+    assert!(report.passed());
+}
+
+/// The access to `shared_array[102]` is outside the region.
+#[test]
+fn test_report_bad_entry_4() {
+    let report = report_testcase("bad_entry_4", &Policy::default());
+    assert!(!report.passed());
+    for violation in &report.violations {
+        assert_eq!(violation.kind, ViolationKind::OutOfRegionAccess);
+        assert_eq!(violation.rule_id, ViolationKind::OutOfRegionAccess.rule_id());
+        assert_eq!(violation.function, "bad_entry_4");
+        assert_eq!(violation.location, None);
+    }
+}
+
+#[test]
+fn test_report_call_0_denied() {
+    let policy = Policy {
+        calls: CallPolicy::Deny(vec!["rand".to_string()]),
+        ..Policy::default()
+    };
+    let report = report_testcase("call_0", &policy);
+    assert_eq!(report.violations.len(), 1);
+    assert_eq!(report.violations[0].kind, ViolationKind::DisallowedCall);
+    assert!(report.violations[0].message.contains("rand"));
+}
+
+/// debug_0 is bad_entry_4 compiled with debug info.
+#[test]
+fn test_report_debug_0_location() {
+    let report = report_testcase("debug_0", &Policy::default());
+    assert!(!report.passed());
+    for violation in &report.violations {
+        let location = violation.location.as_ref().expect("Missing source location");
+        assert!(location.file.ends_with("debug_0.c"));
+        assert_eq!(location.line, 15);
+    }
+}
+
+#[test]
+fn test_report_int_to_ptr_0() {
+    let report = report_testcase("int_to_ptr_0", &Policy::default());
+    assert!(!report.passed());
+    for violation in &report.violations {
+        assert_eq!(violation.kind, ViolationKind::IntToPtrAccess);
+        assert!(violation.message.contains("cast from an integer"));
+    }
+}
+
+#[test]
+fn test_report_gather_0() {
+    let report = report_testcase("gather_0", &Policy::default());
+    assert!(report.violations.iter().any(|violation| violation.kind == ViolationKind::UnanalysableAccess));
+}
+
+#[test]
+fn test_report_call_0_invalid_marker() {
+    let policy = Policy {
+        markers: Markers { begin: "rand".to_string(), end: "utx0".to_string() },
+        ..Policy::default()
+    };
+    let report = report_testcase("call_0", &policy);
+    assert_eq!(report.violations[0].kind, ViolationKind::InvalidMarker);
+}
+
+#[test]
+fn test_report_json() {
+    let report = report_testcase("bad_entry_4", &Policy::default());
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["violations"][0]["kind"], "out-of-region-access");
+}
+
+#[test]
+fn test_report_sarif() {
+    let report = report_testcase("debug_0", &Policy::default());
+    let sarif: serde_json::Value = serde_json::from_str(&report.to_sarif().unwrap()).unwrap();
+    assert_eq!(sarif["version"], "2.1.0");
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["rules"].as_array().unwrap().len(), ViolationKind::ALL.len());
+    let result = &run["results"][0];
+    assert_eq!(result["ruleId"], ViolationKind::OutOfRegionAccess.rule_id());
+    assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 15);
+
+    // The source file is compiled from its directory, so its path is absolute
+    let uri = result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"].as_str().unwrap();
+    assert!(uri.starts_with("file:///"), "{}", uri);
+    assert!(uri.ends_with("/tests/c_files/debug_0.c"), "{}", uri);
+}
+
+/// A location without a column has no `startColumn`.
+#[test]
+fn test_report_sarif_unknown_column() {
+    let report = VerificationReport {
+        violations: vec![Violation {
+            rule_id: ViolationKind::OutOfRegionAccess.rule_id(),
+            kind: ViolationKind::OutOfRegionAccess,
+            function: "f".to_string(),
+            instruction: "store i64 0, ptr %p, align 8".to_string(),
+            message: "Access outside the protected region".to_string(),
+            location: Some(SourceLocation { file: "/src/f.c".to_string(), line: 3, column: 0 }),
+        }],
+    };
+    let sarif: serde_json::Value = serde_json::from_str(&report.to_sarif().unwrap()).unwrap();
+    let region = &sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"];
+    assert_eq!(region["startLine"], 3);
+    assert!(region.get("startColumn").is_none());
+}
@@ -1,79 +1,56 @@
+mod common;
+
 use llvm_sandboxer::runtime;
+use llvm_sandboxer::policy::Policy;
+use llvm_sandboxer::stats::InstrumentationStats;
+use llvm_sandboxer::report;
 
-use std::path::Path;
-use std::process::Command;
-use inkwell::module::Module;
+use std::fs;
+use std::process::{Command, Output};
 use inkwell::context::Context;
 
-/// Compile testcases' C sources.
-fn compile_c_files() {
-    let c_files_dir_path = Path::new("tests/c_files/");
+use common::{compile_ll_to_exec, load_testcase};
 
-    // Compile the C source file
-    Command::new("make")
-        .args(["-C", &c_files_dir_path.to_string_lossy()])
-        .output()
-        .expect("Failed to compile C source file.");
+/// Parse a policy from TOML, e.g. `static_analysis = true`.
+fn policy(toml: &str) -> Policy {
+    Policy::from_toml(toml).unwrap()
 }
 
-/// Compile instrumented LLVMs to executable
-fn compile_ll_to_exec(filepath: &str) -> String {
-    let filepath = Path::new(filepath);
-    let exec_filepath = filepath.with_extension("o");
-
-    // Compile
-    let output = Command::new("clang")
-        .arg(filepath)
-        .arg("-o")
-        .arg(exec_filepath.clone())
-        .output()
-        .expect("Failed to compile LLVMs to executable.");
+/// Instrument a testcase following `policy`.
+///
+/// # Returns
+///
+/// Returns the statistics and the instrumented IR, or the error.
+fn instrument(testcase_name: &str, policy: &Policy) -> Result<(InstrumentationStats, String), String> {
+    let context = Context::create();
+    let module = load_testcase(&context, testcase_name);
 
-    println!("{:?}", output);
+    let stats = runtime::instrument_with_policy(testcase_name, &context, &module, policy)?;
+    assert!(module.verify().is_ok());
 
-    exec_filepath.display().to_string()
+    Ok((stats, module.print_to_string().to_string()))
 }
 
-/// Test one LLVM bitcode file.
-fn instrument_testcase(testcase_name: &str) -> String {
-
-    // Get testcase bitcode path
-    let bitcode_path = format!("target/tests/{}.bc", testcase_name);
-    let bitcode_path = Path::new(&bitcode_path);
-    
-    // Compile if it not exists
-    if !bitcode_path.exists() {
-        compile_c_files();
-    }
-
-    // Parse bitcode
-    let context = Context::create();
-    let module = Module::parse_bitcode_from_path(&bitcode_path, &context).unwrap();
-
-    match runtime::instrument(testcase_name, &context, &module, true) {
-        Ok(()) => println!("Instrumentation completed successfully"),
-        Err(err) => println!("Error occurred: {:?}", err)
-    }
-
-    // Save to file
-    let filepath = format!("target/tests/instrumented/{}_instrumented.ll", testcase_name);
-    let _ = module.print_to_file(filepath.clone());
+/// Save instrumented IR as `target/tests/instrumented/<name>.ll`, compile it
+/// and execute it.
+fn run_ir(ir: &str, name: &str) -> Output {
+    fs::create_dir_all("target/tests/instrumented").unwrap();
+    let ll_filepath = format!("target/tests/instrumented/{}.ll", name);
+    fs::write(&ll_filepath, ir).unwrap();
+    let filepath = compile_ll_to_exec(&ll_filepath);
 
-    return filepath
+    // Execute the instrumented testcase
+    Command::new(filepath.clone())
+        .output()
+        .expect(&format!("Cannot execute {}.", filepath))
 }
 
 // This tests are manual for now, to run one of them use the following command: 
 // `cargo test <test name>`.
 #[test]
 fn test_instrument_bad_entry_0() {
-    let test_case_name = "bad_entry_0";
-    let ll_filepath = instrument_testcase(test_case_name);
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("bad_entry_0", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_0_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
@@ -81,13 +58,8 @@ fn test_instrument_bad_entry_0() {
 
 #[test]
 fn test_instrument_bad_entry_1() {
-    let ll_filepath = instrument_testcase("bad_entry_1");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("bad_entry_1", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_1_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
@@ -95,69 +67,79 @@ fn test_instrument_bad_entry_1() {
 
 #[test]
 fn test_instrument_bad_entry_2() {
-    let ll_filepath = instrument_testcase("bad_entry_2");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("bad_entry_2", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_2_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
 }
 
+/// Rejected at compile time: the protected region is 1 byte, the access 8 bytes.
 #[test]
 fn test_instrument_bad_entry_3() {
-    let ll_filepath = instrument_testcase("bad_entry_3");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+    let err = instrument("bad_entry_3", &policy("static_analysis = true")).unwrap_err();
+    assert!(err.starts_with("Access outside the protected region: ") && err.contains("load i64"), "{}", err);
+}
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+/// Rejected at compile time: `shared_array[102]` is past the protected array.
+#[test]
+fn test_instrument_bad_entry_4() {
+    let err = instrument("bad_entry_4", &policy("static_analysis = true")).unwrap_err();
+    assert!(err.starts_with("Access outside the protected region: ") && err.contains("load i64"), "{}", err);
+}
 
-    // Check that it crashes
-    assert_eq!(output.status.code(), None);
+/// Rejected at compile time: `shared_array[index+1]` is after the protected element.
+#[test]
+fn test_instrument_bad_entry_5() {
+    let err = instrument("bad_entry_5", &policy("static_analysis = true")).unwrap_err();
+    assert!(err.starts_with("Access outside the protected region: ") && err.contains("load i64"), "{}", err);
 }
 
+/// Rejected at compile time: `shared_array[-1]` is before the protected array.
 #[test]
-fn test_instrument_bad_entry_4() {
-    let ll_filepath = instrument_testcase("bad_entry_4");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+fn test_instrument_bad_entry_6() {
+    let err = instrument("bad_entry_6", &policy("static_analysis = true")).unwrap_err();
+    assert!(err.starts_with("Access outside the protected region: ") && err.contains("load i64"), "{}", err);
+}
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+#[test]
+fn test_instrument_bad_entry_7() {
+    let (_, ir) = instrument("bad_entry_7", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_7_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
 }
 
 #[test]
-fn test_instrument_bad_entry_5() {
-    let ll_filepath = instrument_testcase("bad_entry_5");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+fn test_instrument_bad_entry_8() {
+    let (_, ir) = instrument("bad_entry_8", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_8_instrumented");
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+#[test]
+fn test_instrument_bad_entry_9() {
+    let (_, ir) = instrument("bad_entry_9", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_9_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
 }
 
+/// Rejected at compile time: `buf[n + 1]` is past the heap buffer.
 #[test]
-fn test_instrument_bad_entry_6() {
-    let ll_filepath = instrument_testcase("bad_entry_6");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+fn test_instrument_bad_entry_13() {
+    let err = instrument("bad_entry_13", &policy("static_analysis = true")).unwrap_err();
+    assert!(err.starts_with("Access outside the protected region: ") && err.contains("store i64 2"), "{}", err);
+}
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+#[test]
+fn test_instrument_bad_entry_12() {
+    let (_, ir) = instrument("bad_entry_12", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "bad_entry_12_instrumented");
 
     // Check that it crashes
     assert_eq!(output.status.code(), None);
@@ -165,13 +147,8 @@ fn test_instrument_bad_entry_6() {
 
 #[test]
 fn test_instrument_good_entry_0() {
-    let ll_filepath = instrument_testcase("good_entry_0");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("good_entry_0", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_0_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -179,13 +156,8 @@ fn test_instrument_good_entry_0() {
 
 #[test]
 fn test_instrument_good_entry_1() {
-    let ll_filepath = instrument_testcase("good_entry_1");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("good_entry_1", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_1_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -193,13 +165,8 @@ fn test_instrument_good_entry_1() {
 
 #[test]
 fn test_instrument_good_entry_2() {
-    let ll_filepath = instrument_testcase("good_entry_2");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}.", filepath));
+    let (_, ir) = instrument("good_entry_2", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_2_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -207,13 +174,8 @@ fn test_instrument_good_entry_2() {
 
 #[test]
 fn test_instrument_good_entry_3() {
-    let ll_filepath = instrument_testcase("good_entry_3");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    let (_, ir) = instrument("good_entry_3", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_3_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -221,13 +183,8 @@ fn test_instrument_good_entry_3() {
 
 #[test]
 fn test_instrument_good_entry_4() {
-    let ll_filepath = instrument_testcase("good_entry_4");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    let (_, ir) = instrument("good_entry_4", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_4_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -235,13 +192,8 @@ fn test_instrument_good_entry_4() {
 
 #[test]
 fn test_instrument_good_entry_5() {
-    let ll_filepath = instrument_testcase("good_entry_5");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    let (_, ir) = instrument("good_entry_5", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_5_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -249,13 +201,8 @@ fn test_instrument_good_entry_5() {
 
 #[test]
 fn test_instrument_good_entry_6() {
-    let ll_filepath = instrument_testcase("good_entry_6");
-    let filepath = compile_ll_to_exec(&ll_filepath);
-
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    let (_, ir) = instrument("good_entry_6", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_6_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -263,13 +210,53 @@ fn test_instrument_good_entry_6() {
 
 #[test]
 fn test_instrument_good_entry_7() {
-    let ll_filepath = instrument_testcase("good_entry_7");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+    let (_, ir) = instrument("good_entry_7", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_7_instrumented");
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_good_entry_8() {
+    let (_, ir) = instrument("good_entry_8", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_8_instrumented");
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_good_entry_9() {
+    let (_, ir) = instrument("good_entry_9", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_9_instrumented");
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_good_entry_10() {
+    let (_, ir) = instrument("good_entry_10", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_10_instrumented");
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_good_entry_11() {
+    let (_, ir) = instrument("good_entry_11", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_11_instrumented");
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_good_entry_13() {
+    let (_, ir) = instrument("good_entry_13", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "good_entry_13_instrumented");
 
     // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
@@ -277,13 +264,348 @@ fn test_instrument_good_entry_7() {
 
 #[test]
 fn test_instrument_phi_0() {
-    let ll_filepath = instrument_testcase("phi_0");
-    let filepath = compile_ll_to_exec(&ll_filepath);
+    let (_, ir) = instrument("phi_0", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "phi_0_instrumented");
 
-    // Execute the instrumented testcase
-    let output = Command::new(filepath.clone())
-        .output()
-        .expect(&format!("Cannot execute {}", filepath));
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_instrument_call_0_denied() {
+    // The function must not be instrumented
+    let result = instrument("call_0", &policy("calls = { deny = [\"rand\"] }"));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_instrument_stack_0_stack_bounds() {
+    let (_, ir) = instrument("stack_0", &policy("static_analysis = true\ncheck_stack_bounds = true")).unwrap();
+    let output = run_ir(&ir, "stack_0_stack_bounds_instrumented");
 
+    // Check that it doesn't crash
     assert_eq!(output.status.code(), Some(0));
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_instrument_stack_1_stack_bounds() {
+    let (_, ir) = instrument("stack_1", &policy("static_analysis = true\ncheck_stack_bounds = true")).unwrap();
+    let output = run_ir(&ir, "stack_1_stack_bounds_instrumented");
+
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+/// Only the store at a constant offset inside the stack array is not checked.
+#[test]
+fn test_stats_stack_3_stack_bounds() {
+    let stats = instrument("stack_3", &policy("check_stack_bounds = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["store"].stack, 1);
+    assert_eq!(stats.accesses["store"].checked, 1);
+}
+
+/// The address of a stack slot is stored through a pointer outside the region.
+#[test]
+fn test_instrument_stack_2() {
+    let (_, ir) = instrument("stack_2", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "stack_2_instrumented");
+
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+/// Only the pointer operand of the store is a stack slot or not.
+#[test]
+fn test_stats_stack_2() {
+    let stats = instrument("stack_2", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["store"].checked, 1);
+}
+
+/// `shared_array[1] += 1` is a load and a store, both inside the protected array.
+#[test]
+fn test_stats_good_entry_3() {
+    let stats = instrument("good_entry_3", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.function, "good_entry_3");
+    assert_eq!(stats.accesses["load"].elided, 1);
+    assert_eq!(stats.accesses["store"].elided, 1);
+    assert_eq!(stats.total().checked, 0);
+}
+
+#[test]
+fn test_stats_good_entry_3_without_static_analysis() {
+    let stats = instrument("good_entry_3", &Policy::default()).unwrap().0;
+
+    assert_eq!(stats.accesses["load"].checked, 1);
+    assert_eq!(stats.accesses["store"].checked, 1);
+    assert_eq!(stats.total().elided, 0);
+}
+
+/// The struct fields are at constant offsets inside the protected struct.
+#[test]
+fn test_stats_good_entry_13() {
+    let stats = instrument("good_entry_13", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.total().checked, 0);
+}
+
+/// The elements of the protected row are at constant offsets inside it.
+#[test]
+fn test_stats_good_entry_14() {
+    let stats = instrument("good_entry_14", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["load"].elided, 1);
+    assert_eq!(stats.accesses["store"].elided, 2);
+    assert_eq!(stats.total().checked, 0);
+}
+
+/// The loop index is bounded by the exit condition of the loop.
+#[test]
+fn test_stats_phi_0() {
+    let stats = instrument("phi_0", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.total().checked, 0);
+}
+
+/// Splitting the loop body rebuilds the phi of the loop index, the second
+/// store is still proven inside the protected region.
+#[test]
+fn test_stats_phi_1() {
+    let stats = instrument("phi_1", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["store"].checked, 1);
+    assert_eq!(stats.accesses["store"].elided, 1);
+}
+
+/// Accesses to the heap buffer are bounded by its length, for every `n` up to 1000.
+#[test]
+fn test_stats_good_entry_16() {
+    let stats = instrument("good_entry_16", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.total().checked, 0);
+}
+
+/// The length cannot wrap for any `uint32_t` value of `n`.
+#[test]
+fn test_stats_good_entry_17() {
+    let stats = instrument("good_entry_17", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.total().checked, 0);
+}
+
+/// The length may wrap, so the access to the heap buffer is checked.
+#[test]
+fn test_stats_wrap_0() {
+    let stats = instrument("wrap_0", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["store"].checked, 1);
+}
+
+/// For `n = 2^61` the length wraps to 0 and `buf[n - 1]` is before the buffer.
+#[test]
+fn test_instrument_wrap_0() {
+    let (_, ir) = instrument("wrap_0", &policy("static_analysis = true")).unwrap();
+    let output = run_ir(&ir, "wrap_0_instrumented");
+
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+/// Accesses through pointers cast from integers are checked even with static analysis.
+#[test]
+fn test_stats_int_to_ptr_0() {
+    let stats = instrument("int_to_ptr_0", &policy("static_analysis = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["load"].checked, 1);
+    assert_eq!(stats.accesses["store"].checked, 1);
+    assert_eq!(stats.total().elided, 0);
+}
+
+/// The load from the constant lookup table is not checked.
+#[test]
+fn test_stats_const_0_read_constant_globals() {
+    let stats = instrument("const_0", &policy("read_constant_globals = true")).unwrap().0;
+
+    // The table is loaded, `shared_array[1]` loaded and stored
+    assert_eq!(stats.accesses["load"].exempt, 1);
+    assert_eq!(stats.accesses["load"].checked, 1);
+    assert_eq!(stats.accesses["store"].checked, 1);
+}
+
+/// The load from the constant lookup table is not proven inside it, so it is checked.
+#[test]
+fn test_stats_const_1_read_constant_globals() {
+    let stats = instrument("const_1", &policy("read_constant_globals = true")).unwrap().0;
+
+    assert_eq!(stats.accesses["load"].exempt, 0);
+    assert_eq!(stats.accesses["load"].checked, 2);
+}
+
+/// `table[4]` is read past the constant table.
+#[test]
+fn test_instrument_const_1_read_constant_globals() {
+    let (_, ir) = instrument("const_1", &policy("read_constant_globals = true")).unwrap();
+    let output = run_ir(&ir, "const_1_read_constants_instrumented");
+
+    // Check that it crashes
+    assert_eq!(output.status.code(), None);
+}
+
+/// The loop is vectorized with masked loads and stores, checked on the whole vector.
+#[test]
+fn test_stats_good_entry_9() {
+    let stats = instrument("good_entry_9", &Policy::default()).unwrap().0;
+
+    assert!(stats.accesses.get("masked_load").is_some_and(|masked_loads| masked_loads.checked > 0));
+    assert!(stats.accesses.get("masked_store").is_some_and(|masked_stores| masked_stores.checked > 0));
+}
+
+#[test]
+fn test_stats_bad_entry_8() {
+    let stats = instrument("bad_entry_8", &Policy::default()).unwrap().0;
+
+    assert!(stats.accesses.get("masked_load").is_some_and(|masked_loads| masked_loads.checked > 0));
+    assert!(stats.accesses.get("masked_store").is_some_and(|masked_stores| masked_stores.checked > 0));
+}
+
+/// gather_0 needs AVX-512 to run, the lanes checks are only checked to be valid IR.
+#[test]
+fn test_instrument_gather_0() {
+    let stats = instrument("gather_0", &Policy::default()).unwrap().0;
+
+    assert!(stats.accesses.get("gather").is_some_and(|gathers| gathers.checked > 0));
+}
+
+/// scatter_0 needs AVX-512 to run, the lanes checks are only checked to be valid IR.
+#[test]
+fn test_instrument_scatter_0() {
+    let stats = instrument("scatter_0", &Policy::default()).unwrap().0;
+
+    assert!(stats.accesses.get("scatter").is_some_and(|scatters| scatters.checked > 0));
+}
+
+/// `config[2]` is inside the exempt global, `config[index]` is checked.
+#[test]
+fn test_stats_exempt_0() {
+    let stats = instrument("exempt_0", &policy("exempt_globals = [\"config\"]")).unwrap().0;
+
+    assert_eq!(stats.accesses["load"].exempt, 1);
+    assert_eq!(stats.accesses["store"].exempt, 0);
+}
+
+#[test]
+fn test_stats_json() {
+    let stats = instrument("good_entry_3", &policy("static_analysis = true")).unwrap().0;
+    let json = InstrumentationStats::to_json(&[stats]).unwrap();
+
+    assert!(json.contains("\"function\": \"good_entry_3\""));
+    assert!(json.contains("\"elided\": 1"));
+}
+
+/// debug_0 is compiled with debug info: the checks inserted before the
+/// accesses keep their source location, and the module stays valid.
+#[test]
+fn test_instrument_debug_0_locations() {
+    let context = Context::create();
+    let module = load_testcase(&context, "debug_0");
+
+    let stats = runtime::instrument("debug_0", &context, &module, false).unwrap();
+    assert_eq!(stats.total().checked, 2);
+    assert!(module.verify().is_ok());
+
+    // Every instruction but the shared abort block is attributed to the source
+    let function = module.get_function("debug_0").unwrap();
+    for bb in function.get_basic_blocks() {
+        if bb.get_name().to_str().unwrap() == "__sandbox.abort" {
+            continue;
+        }
+        for instr in bb.get_instructions() {
+            assert!(report::get_source_location(instr).is_some(), "{:?} has no location", instr);
+        }
+    }
+}
+
+/// In diagnostic mode, the failed check of `shared_array[102]` prints its
+/// source location and the protected region before aborting.
+#[test]
+fn test_instrument_debug_0_diagnostics() {
+    let (_, ir) = instrument("debug_0", &policy("diagnostics = true")).unwrap();
+    let output = run_ir(&ir, "debug_0_diagnostics_instrumented");
+
+    // Check that it crashes with a report
+    assert_eq!(output.status.code(), None);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("debug_0.c:15:"), "{}", stderr);
+    assert!(stderr.contains("in debug_0: 8 bytes accessed at"), "{}", stderr);
+    assert!(stderr.contains("outside the region of 800 bytes"), "{}", stderr);
+}
+
+/// phi_0 has a loop, whose phi instructions are updated when blocks are split.
+#[test]
+fn test_instrument_phi_0_names() {
+    let context = Context::create();
+    let module = load_testcase(&context, "phi_0");
+    let function = module.get_function("phi_0").unwrap();
+
+    let block_names: Vec<String> = function.get_basic_blocks().iter()
+        .map(|bb| bb.get_name().to_str().unwrap().to_string())
+        .collect();
+
+    runtime::instrument("phi_0", &context, &module, false).unwrap();
+    assert!(module.verify().is_ok());
+
+    // The original blocks keep their names, the generated ones have the reserved prefix
+    for bb in function.get_basic_blocks() {
+        let name = bb.get_name().to_str().unwrap().to_string();
+        assert!(block_names.contains(&name) || name.starts_with("__sandbox."), "unexpected block {}", name);
+    }
+    for name in block_names {
+        assert!(function.get_basic_blocks().iter().any(|bb| bb.get_name().to_str().unwrap() == name), "block {} was renamed", name);
+    }
+
+    // The generated names do not depend on the run
+    let (_, second) = instrument("phi_0", &Policy::default()).unwrap();
+    assert_eq!(module.print_to_string().to_string(), second);
+    assert!(second.contains("@__sandbox.protected_ptr"));
+}
+
+/// Instrumenting a function twice fails without modifying the module.
+#[test]
+fn test_instrument_twice() {
+    let context = Context::create();
+    let module = load_testcase(&context, "good_entry_0");
+
+    assert!(!runtime::is_instrumented(&module, "good_entry_0"));
+    runtime::instrument("good_entry_0", &context, &module, false).unwrap();
+    assert!(runtime::is_instrumented(&module, "good_entry_0"));
+
+    let instrumented = module.print_to_string().to_string();
+    assert!(runtime::instrument("good_entry_0", &context, &module, false).is_err());
+    assert_eq!(module.print_to_string().to_string(), instrumented);
+}
+
+/// abort_0 calls `abort` itself: the checks reuse its declaration.
+#[test]
+fn test_instrument_abort_0() {
+    let (_, ir) = instrument("abort_0", &Policy::default()).unwrap();
+    assert!(!ir.contains("@abort.1"));
+
+    let output = run_ir(&ir, "abort_0_instrumented");
+
+    // Check that it doesn't crash
+    assert_eq!(output.status.code(), Some(0));
+}
+
+/// `rand` is declared as `i32 ()`, it cannot be the violation handler.
+#[test]
+fn test_instrument_incompatible_handler() {
+    let context = Context::create();
+    let module = load_testcase(&context, "call_0");
+
+    let policy = policy("violation_handler = \"rand\"");
+
+    let result = runtime::instrument_with_policy("call_0", &context, &module, &policy);
+    assert!(result.unwrap_err().contains("rand"));
+    assert!(!runtime::is_instrumented(&module, "call_0"));
+}
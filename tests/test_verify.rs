@@ -1,34 +1,28 @@
+mod common;
+
 use llvm_sandboxer::static_checks;
+use llvm_sandboxer::policy::{CallPolicy, Markers, Policy};
 
-use std::path::Path;
-use std::process::Command;
-use inkwell::module::Module;
 use inkwell::context::Context;
 
-/// Compile testcases' C sources.
-fn compile_c_files() {
-    let c_files_dir_path = Path::new("tests/c_files/");
-
-    // Compile the C source file
-    Command::new("make")
-        .args(["-C", &c_files_dir_path.to_string_lossy()])
-        .output()
-        .expect("Failed to compile C source file");
-}
+use common::load_testcase;
 
-/// Test one LLVM bitcode file.
+/// Test one testcase.
 fn verify_testcase(testcase_name: &str) -> bool {
-    let bitcode_path = format!("target/tests/{}.bc", testcase_name);
-    let bitcode_path = Path::new(&bitcode_path);
-    if !bitcode_path.exists() {
-        compile_c_files();
-    }
     let context = Context::create();
-    let module = Module::parse_bitcode_from_path(&bitcode_path, &context).unwrap();
+    let module = load_testcase(&context, testcase_name);
     let function = module.get_function(testcase_name).unwrap();
     return static_checks::verify(module, function);
 }
 
+/// Test one testcase with a policy.
+fn verify_testcase_with_policy(testcase_name: &str, policy: &Policy) -> bool {
+    let context = Context::create();
+    let module = load_testcase(&context, testcase_name);
+    let function = module.get_function(testcase_name).unwrap();
+    return static_checks::verify_with_policy(module, function, policy);
+}
+
 /// Test rejection of unprotected memory. 
 /// bad_entry_0 has no call to utx0() or utx1(), therefore no memory protection.
 #[test]
@@ -66,6 +60,17 @@ fn test_bad_entry_6() {
     assert_eq!(verify_testcase("bad_entry_6"), false);
 }
 
+#[test]
+fn test_bad_entry_7() {
+    assert_eq!(verify_testcase("bad_entry_7"), false);
+}
+
+/// The access is guarded by `index <= 100`, one element too many.
+#[test]
+fn test_bad_entry_9() {
+    assert_eq!(verify_testcase("bad_entry_9"), false);
+}
+
 #[test]
 fn test_good_entry_0() {
     assert_eq!(verify_testcase("good_entry_0"), true);
@@ -87,12 +92,11 @@ fn test_good_entry_3() {
     assert_eq!(verify_testcase("good_entry_3"), true);
 }
 
-/* This does not pass
+/// The access is guarded by `index < 100`.
 #[test]
 fn test_good_entry_4() {
     assert_eq!(verify_testcase("good_entry_4"), true);
 }
-*/
 
 #[test]
 fn test_good_entry_5() {
@@ -108,3 +112,230 @@ fn test_good_entry_6() {
 fn test_good_entry_7() {
     assert_eq!(verify_testcase("good_entry_7"), true);
 }
+
+#[test]
+fn test_good_entry_8() {
+    assert_eq!(verify_testcase("good_entry_8"), true);
+}
+
+/// The protected region and the access are both relative to `index`.
+#[test]
+fn test_good_entry_10() {
+    assert_eq!(verify_testcase("good_entry_10"), true);
+}
+
+/// The access is only reached if `index >= 100` is false.
+#[test]
+fn test_good_entry_11() {
+    assert_eq!(verify_testcase("good_entry_11"), true);
+}
+
+/// `i` is only bounded by `i < j` and `j < 100`, which needs the solver.
+#[cfg(feature = "smt")]
+#[test]
+fn test_good_entry_12() {
+    assert_eq!(verify_testcase("good_entry_12"), true);
+}
+
+/// Accesses to the fields of a protected struct.
+#[test]
+fn test_good_entry_13() {
+    assert_eq!(verify_testcase("good_entry_13"), true);
+}
+
+/// `ring.tail` is outside of the protected `ring.buf`.
+#[test]
+fn test_bad_entry_10() {
+    assert_eq!(verify_testcase("bad_entry_10"), false);
+}
+
+/// Accesses to a protected row of a matrix.
+#[test]
+fn test_good_entry_14() {
+    assert_eq!(verify_testcase("good_entry_14"), true);
+}
+
+/// `shared_matrix[3][0]` is outside of the protected `shared_matrix[2]`.
+#[test]
+fn test_bad_entry_11() {
+    assert_eq!(verify_testcase("bad_entry_11"), false);
+}
+
+/// `shared_array[i]` for `0 <= i < SIZE` is inside the protected array.
+#[test]
+fn test_phi_0() {
+    assert_eq!(verify_testcase("phi_0"), true);
+}
+
+/// The loop goes past the protected half of the array.
+#[test]
+fn test_bad_entry_12() {
+    assert_eq!(verify_testcase("bad_entry_12"), false);
+}
+
+/// The protected region is given by a pointer argument.
+#[test]
+fn test_good_entry_15() {
+    assert_eq!(verify_testcase("good_entry_15"), true);
+}
+
+/// The protected region is a heap buffer of `n + 1` elements.
+#[test]
+fn test_good_entry_16() {
+    assert_eq!(verify_testcase("good_entry_16"), true);
+}
+
+/// `buf[n + 1]` is past the heap buffer of `n + 1` elements.
+#[test]
+fn test_bad_entry_13() {
+    assert_eq!(verify_testcase("bad_entry_13"), false);
+}
+
+/// The length of the heap buffer of `n + 1` elements cannot wrap, `n` is a
+/// `uint32_t` widened to 64 bits.
+#[test]
+fn test_good_entry_17() {
+    assert_eq!(verify_testcase("good_entry_17"), true);
+}
+
+/// `n * 8` wraps for large `n`, so `buf[n - 1]` is not proven inside.
+#[test]
+fn test_wrap_0() {
+    assert_eq!(verify_testcase("wrap_0"), false);
+}
+
+/// The accessed pointer is cast from an integer, even if it is the protected one.
+#[test]
+fn test_int_to_ptr_0() {
+    assert_eq!(verify_testcase("int_to_ptr_0"), false);
+}
+
+/// The constant lookup table is outside of the protected region.
+#[test]
+fn test_const_0() {
+    assert_eq!(verify_testcase("const_0"), false);
+}
+
+#[test]
+fn test_const_0_read_constant_globals() {
+    let policy = Policy {
+        read_constant_globals: true,
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("const_0", &policy), true);
+}
+
+/// `table[index]` is not proven inside the constant table, so it is checked.
+#[test]
+fn test_const_1_read_constant_globals() {
+    let policy = Policy {
+        read_constant_globals: true,
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("const_1", &policy), false);
+}
+
+/// `malloc` and `free` are called outside of the transaction.
+#[test]
+fn test_good_entry_16_allow_none() {
+    let policy = Policy {
+        calls: CallPolicy::Allow(Vec::new()),
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("good_entry_16", &policy), true);
+}
+
+/// `config[index]` is not proven inside the exempt global.
+#[test]
+fn test_exempt_0() {
+    let policy = Policy {
+        exempt_globals: vec!["config".to_string()],
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("exempt_0", &policy), false);
+}
+
+/// `rand` does not take a pointer and a length.
+#[test]
+fn test_call_0_invalid_marker() {
+    let policy = Policy {
+        markers: Markers { begin: "rand".to_string(), end: "utx0".to_string() },
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("call_0", &policy), false);
+}
+
+#[test]
+fn test_call_0_allow_all() {
+    assert_eq!(verify_testcase_with_policy("call_0", &Policy::default()), true);
+}
+
+#[test]
+fn test_call_0_allowed() {
+    let policy = Policy {
+        calls: CallPolicy::Allow(vec!["rand".to_string()]),
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("call_0", &policy), true);
+}
+
+#[test]
+fn test_call_0_not_allowed() {
+    let policy = Policy {
+        calls: CallPolicy::Allow(vec!["memcpy".to_string()]),
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("call_0", &policy), false);
+}
+
+#[test]
+fn test_call_0_denied() {
+    let policy = Policy {
+        calls: CallPolicy::Deny(vec!["rand".to_string()]),
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("call_0", &policy), false);
+}
+
+/// bad_entry_0 has no memory protection, but only accesses `shared_array`.
+#[test]
+fn test_bad_entry_0_exempt_global() {
+    let policy = Policy {
+        exempt_globals: vec!["shared_array".to_string()],
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("bad_entry_0", &policy), true);
+}
+
+/// Both edges of the branch on `index < 100` lead to the access.
+#[test]
+fn test_branch_0() {
+    assert_eq!(verify_testcase("branch_0"), false);
+}
+
+/// Both edges of the latch lead back to the loop header, `i` is not bounded.
+#[test]
+fn test_loop_0() {
+    assert_eq!(verify_testcase("loop_0"), false);
+}
+
+/// `rand` is invoked inside the transaction.
+#[test]
+fn test_invoke_0_denied() {
+    let policy = Policy {
+        calls: CallPolicy::Deny(vec!["rand".to_string()]),
+        ..Policy::default()
+    };
+    assert_eq!(verify_testcase_with_policy("invoke_0", &policy), false);
+}
+
+#[test]
+fn test_invoke_0_allow_all() {
+    assert_eq!(verify_testcase("invoke_0"), true);
+}
+
+/// Indirect invokes are not allowed, even by `"allow_all"`.
+#[test]
+fn test_invoke_1_allow_all() {
+    assert_eq!(verify_testcase("invoke_1"), false);
+}